@@ -1,8 +1,30 @@
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use clap::{command, Parser};
+use clap::{ArgGroup, Parser};
 
-use crate::ssem::simulator::Simulator;
+use crate::ssem::crt::{Glyphs, TubeRenderer};
+use crate::ssem::diff::StoreDiff;
+use crate::ssem::display::{annotations, Columns, WordFormatter};
+use crate::ssem::faults::{run_with_faults, FaultOptions, ScheduledFault};
+use crate::ssem::frames::{FrameExporter, FrameOptions, Interval, SpotStyle};
+use crate::ssem::heatmap::{Heatmap, Scale};
+use crate::ssem::image::{write_block, BlockFormat, Dithering, Image, ImportOptions};
+use crate::ssem::interrupt::Interrupt;
+use crate::ssem::live::{read_commands, run_live, Poke};
+use crate::ssem::narrator::Narrator;
+use crate::ssem::overflow::OverflowMode;
+use crate::ssem::panel::{script_from_file, FrontPanel};
+use crate::ssem::peripheral::{Mapping, PeripheralKind};
+use crate::ssem::profile::Profile;
+use crate::ssem::simulator::{Simulator, StopReason};
+use crate::ssem::throttle::run_paced;
+use crate::ssem::timing::{HumanDuration, TimingModel};
+use crate::ssem::trace::{OpcodeSet, Span, TraceFilter, TraceFormat, TraceWriter};
+use crate::ssem::tui::{run_tui, Tui};
+use crate::ssem::vcd::{VcdOptions, VcdWriter};
 
 pub mod ssem;
 
@@ -11,40 +33,667 @@ extern crate lazy_static;
 
 #[derive(Parser)]
 #[command(author, about, long_about = None)]
+#[command(group(ArgGroup::new("faults").multiple(true)))]
 struct Args {
     /// Stop after this amount of cycles
     #[arg(short, long, value_name = "NUM", default_value_t = 100_000_000)]
     max_cycles: u32,
 
-    /// Input file to initialize the store. Can be .asm or .snp format
-    #[arg(value_name = "FILE")]
-    file: PathBuf,
+    /// Behaviour of the machine on details implementations disagree on: 1948, 1998 or sharp
+    #[arg(long, value_name = "NAME", default_value = "1948")]
+    profile: Profile,
+
+    /// What to do when LDN or SUB overflow 32 bits: wrap (silently), trap (stop the machine) or log
+    #[arg(long, value_name = "MODE", default_value = "wrap")]
+    overflow: OverflowMode,
+
+    /// Fill the words the file doesn't define with pseudo-random noise generated from this seed, like a Williams
+    /// tube at power-on
+    #[arg(long, value_name = "SEED")]
+    random_store: Option<u64>,
+
+    /// Report instructions reading words that neither the file nor a STO ever set
+    #[arg(long)]
+    track_uninitialized: bool,
+
+    /// Probability at each cycle that a random bit of the store flips
+    #[arg(long, value_name = "PROBABILITY", group = "faults")]
+    fault_rate: Option<f64>,
+
+    /// Flip a bit of the store before the given cycle, a random one if BIT is not given. Can be repeated
+    #[arg(long, value_name = "ADDR[:BIT]@CYCLE", group = "faults")]
+    fault: Vec<ScheduledFault>,
+
+    /// Probability at each cycle that a charged spot of the store leaks, turning a 1 into a 0
+    #[arg(long, value_name = "PROBABILITY", group = "faults")]
+    charge_loss: Option<f64>,
+
+    /// Disturb a bit of a line once its neighbours were accessed this many times since it was last accessed
+    #[arg(long, value_name = "NUM", group = "faults")]
+    read_around_ratio: Option<u32>,
+
+    /// Seed of the faults drawn at random
+    #[arg(long, value_name = "SEED", default_value_t = 0)]
+    fault_seed: u64,
+
+    /// Write VALUE to the store line ADDR before the given cycle. Can be repeated
+    #[arg(long, value_name = "ADDR=VALUE@CYCLE", conflicts_with = "faults")]
+    poke: Vec<Poke>,
+
+    /// Read poke, peek, pause and resume commands from the standard input while running
+    #[arg(long, conflicts_with = "faults")]
+    live: bool,
+
+    /// Read the live commands from this file or FIFO instead of the standard input
+    #[arg(long, value_name = "PATH", conflicts_with = "faults")]
+    live_input: Option<PathBuf>,
+
+    /// Map a store line to a peripheral: input or output (numbers, one per line), console (Manchester teleprinter
+    /// characters) or cycles (cycle counter). Can be repeated
+    #[arg(long, value_name = "ADDR=KIND", conflicts_with = "faults")]
+    map: Vec<Mapping>,
+
+    /// Stop when the machine goes back to a state it has already been in, as it would then loop forever. Programs
+    /// waiting for live commands or pokes would be stopped before getting them
+    #[arg(long, conflicts_with_all = ["live", "live_input", "poke"])]
+    detect_loops: bool,
+
+    /// Wait for a GDB remote debugger instead of running. ADDRESS is a port, HOST:PORT or unix:PATH
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "faults")]
+    gdb: Option<String>,
+
+    /// Serve the Debug Adapter Protocol on the standard input and output instead of running.
+    /// The program is given by the launch request
+    #[arg(long, conflicts_with_all = ["gdb", "faults"])]
+    dap: bool,
+
+    /// Operate the front panel as described by this script instead of running. The operator procedure is read from
+    /// the comments of .snp and .asm files. FILE, if given, is loaded beforehand
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["gdb", "dap", "faults"])]
+    panel_script: Option<PathBuf>,
+
+    /// Record the last NUM cycles to allow reverse execution from the debugger
+    #[arg(long, value_name = "NUM")]
+    history: Option<usize>,
+
+    /// Explain every executed instruction in plain English
+    #[arg(long)]
+    narrate: bool,
+
+    /// Write a record for each executed instruction
+    #[arg(long)]
+    trace: bool,
+
+    /// Format of the trace records: text, csv or json (one object per line)
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    trace_format: TraceFormat,
+
+    /// Write the trace to this file instead of the standard output
+    #[arg(long, value_name = "PATH")]
+    trace_file: Option<PathBuf>,
+
+    /// Only trace instructions at these addresses, e.g. 10-20
+    #[arg(long, value_name = "START-END")]
+    trace_addresses: Option<Span<i32>>,
+
+    /// Only trace these opcodes, e.g. LDN,SUB
+    #[arg(long, value_name = "OPCODES")]
+    trace_opcodes: Option<OpcodeSet>,
+
+    /// Only trace these cycles, e.g. 1000-2000
+    #[arg(long, value_name = "START-END")]
+    trace_cycles: Option<Span<u64>>,
+
+    /// Record A, CI, the present instruction and some store lines to a VCD waveform file
+    #[arg(long, value_name = "PATH")]
+    vcd: Option<PathBuf>,
+
+    /// Store lines recorded in the VCD file, e.g. 0,28-31
+    #[arg(long, value_name = "LINES", value_delimiter = ',')]
+    vcd_lines: Vec<Span<i32>>,
+
+    /// Cycles recorded in the VCD file
+    #[arg(long, value_name = "START-END", default_value = "0-99999")]
+    vcd_cycles: Span<u64>,
+
+    /// Use simulated time (see --timing) for VCD timestamps instead of cycles
+    #[arg(long)]
+    vcd_historic_time: bool,
+
+    /// Export pictures of the store tube: an animated .gif, or a .pbm or .png sequence numbered before the extension
+    #[arg(long, value_name = "PATH")]
+    frames: Option<PathBuf>,
+
+    /// Time between two pictures: a number of cycles, or a simulated duration (see --timing) such as 40ms
+    #[arg(long, value_name = "INTERVAL", default_value = "50ms")]
+    frame_every: Interval,
+
+    /// Cycles pictured
+    #[arg(long, value_name = "START-END", default_value = "0-99999")]
+    frame_cycles: Span<u64>,
+
+    /// Side of a spot in the pictures, in pixels
+    #[arg(long, value_name = "PIXELS", default_value = "8", value_parser = clap::value_parser!(u32).range(1..=64))]
+    frame_pixel_size: u32,
+
+    /// How the spots are drawn: blocks, or dots and dashes as on the tube
+    #[arg(long, value_name = "STYLE", default_value = "dots")]
+    frame_style: SpotStyle,
+
+    /// Timing model used to report simulated time: 1948, 1998 or a speed such as 700ips
+    #[arg(long, value_name = "MODEL", default_value = "1948")]
+    timing: TimingModel,
+
+    /// Override the number of beats per instruction of the timing model
+    #[arg(long, value_name = "NUM")]
+    beats_per_instruction: Option<u32>,
+
+    /// Override the duration of a beat of the timing model, in microseconds
+    #[arg(long, value_name = "MICROSECONDS")]
+    beat_time: Option<f64>,
+
+    /// Run at this many instructions per second instead of as fast as possible
+    #[arg(long, value_name = "NUM", conflicts_with_all = ["realtime", "faults"])]
+    ips: Option<f64>,
+
+    /// Run at the speed of the timing model (see --timing)
+    #[arg(long, conflicts_with = "faults")]
+    realtime: bool,
+
+    /// Multiply the speed given by --ips or --realtime
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    speed: f64,
+
+    /// Columns of the final state listing, among ssem, modern, decimal, hex, teleprinter, instruction and annotation,
+    /// e.g. ssem,decimal,instruction or all
+    #[arg(long, value_name = "COLUMNS")]
+    display: Option<Columns>,
+
+    /// Also draw the final state on the store, accumulator and control tubes with blocks, braille or dots. Also
+    /// sets the glyphs of --tui, which draws dots by default.
+    #[arg(long, value_name = "GLYPHS")]
+    tubes: Option<Glyphs>,
+
+    /// Draw the tubes of --tubes and --tui in phosphor green with ANSI colors
+    #[arg(long)]
+    green: bool,
+
+    /// Operate the front panel in a full-screen terminal interface instead of running, with the tubes updating live
+    #[arg(long, conflicts_with_all = ["gdb", "dap", "panel_script", "faults", "live", "live_input", "poke"])]
+    tui: bool,
+
+    /// Stop the run once this much wall-clock time has elapsed, e.g. 30s or 1m 30s
+    #[arg(long, value_name = "DURATION")]
+    timeout: Option<HumanDuration>,
+
+    /// Save the final store to this .snp file, even when the run is interrupted with Ctrl-C
+    #[arg(long, value_name = "PATH")]
+    snapshot: Option<PathBuf>,
+
+    /// Count how many times each store line is executed, read and written, and draw the counts as a heatmap
+    /// after the final state
+    #[arg(long)]
+    heatmap: bool,
+
+    /// Also draw the heatmap of --heatmap to this SVG file
+    #[arg(long, value_name = "PATH")]
+    heatmap_svg: Option<PathBuf>,
+
+    /// Scale of the heatmap colors: log, for lines used a few times to show next to inner loops, or linear
+    #[arg(long, value_name = "SCALE", default_value = "log")]
+    heatmap_scale: Scale,
+
+    /// Compare the store loaded from FILE with the one of this .asm, .snp or .tp file instead of running, listing
+    /// the words that differ side by side
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["dap", "panel_script", "gdb", "tui"])]
+    diff: Option<PathBuf>,
+
+    /// After the run, list the words of the store that differ from the ones loaded from FILE
+    #[arg(long)]
+    diff_run: bool,
+
+    /// Convert a PBM or PGM image, up to 32 pixels wide, into a block of store words instead of running. Each row
+    /// of pixels becomes a word, bright pixels being lit spots
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["file", "dap", "panel_script"])]
+    import_image: Option<PathBuf>,
+
+    /// Write the block of --import-image to this .asm or .snp file instead of the standard output
+    #[arg(long, value_name = "PATH", requires = "import_image")]
+    import_output: Option<PathBuf>,
+
+    /// Format of the block of --import-image when written to the standard output: asm or snp. The extension of
+    /// --import-output gives it otherwise
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "asm",
+        conflicts_with = "import_output"
+    )]
+    import_format: BlockFormat,
+
+    /// Store line of the first word of the block of --import-image
+    #[arg(long, value_name = "ADDR", default_value_t = 0)]
+    import_address: i32,
+
+    /// Brightness from 0 (black) to 1 (white) above which a pixel of --import-image is lit
+    #[arg(long, value_name = "LEVEL", default_value_t = 0.5)]
+    threshold: f64,
+
+    /// Dithering of the grayscale images of --import-image: none, floyd-steinberg or ordered
+    #[arg(long, value_name = "METHOD", default_value = "none")]
+    dither: Dithering,
+
+    /// Light the dark pixels of --import-image instead, for pictures drawn in black on white
+    #[arg(long)]
+    invert: bool,
+
+    /// Input file to initialize the store. Can be .asm, .snp or .tp format
+    #[arg(value_name = "FILE", required_unless_present_any = ["dap", "panel_script", "import_image"])]
+    file: Option<PathBuf>,
 }
 
 fn main() {
+    let args = Args::parse();
+
+    // The standard output carries the protocol messages
+    if args.dap {
+        if let Err(e) = ssem::dap::serve(args.history, args.profile) {
+            eprintln!("DAP server error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // The block may be written to the standard output
+    if let Some(path) = &args.import_image {
+        if let Err(e) = import_image(path, &args) {
+            eprintln!("Unable to import '{}': {e}", path.display());
+            std::process::exit(1);
+        }
+        return;
+    }
+
     println!();
     println!("//// ssem-simulator ////");
-    println!("");
+    println!();
     println!("This is a very early build.");
     println!("Visit the following repository for a fully functional simulator:");
     println!("    https://github.com/pfaivre/manchester-baby-sim");
     println!();
 
-    let args = Args::parse();
+    if let Some(path) = args.panel_script {
+        let mut simulator = match &args.file {
+            Some(file) => Simulator::from_file(file),
+            None => Simulator::new(),
+        };
+        simulator.set_profile(args.profile);
+        let mut panel = FrontPanel::new(simulator, args.max_cycles);
+        let directory = path.parent().unwrap_or(Path::new("."));
+        let result = script_from_file(&path)
+            .and_then(|script| panel.run_script(&script, directory, &mut io::stdout().lock()));
+        if let Err(e) = result {
+            eprintln!("Unable to run '{}': {e}", path.display());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let file = args.file.expect("An input file is required");
+    if let Some(other) = &args.diff {
+        let diff = StoreDiff::from_files(&file, other);
+        print_diff(&diff, "The stores are identical");
+        return;
+    }
+
+    let (mut simulator, source_map) = Simulator::from_file_mapped(&file);
+    if let Some(seed) = args.random_store {
+        simulator.store.randomize_unspecified(&source_map, seed);
+    }
+    if args.track_uninitialized {
+        simulator.enable_uninitialized_tracking(&source_map);
+    }
+    simulator.set_profile(args.profile);
+    simulator.overflow_mode = args.overflow;
+
+    let reads_stdin =
+        |kind: &PeripheralKind| matches!(kind, PeripheralKind::Input | PeripheralKind::Console);
+    let mut kinds: Vec<PeripheralKind> = Vec::new();
+    for mapping in &args.map {
+        if !kinds.contains(&mapping.kind) {
+            kinds.push(mapping.kind);
+        }
+    }
+    if kinds.iter().filter(|kind| reads_stdin(kind)).count() > 1
+        || ((args.tui || args.live && args.live_input.is_none()) && kinds.iter().any(reads_stdin))
+    {
+        eprintln!("Only one of the input and console peripherals, the live commands and the terminal interface can read the standard input");
+        std::process::exit(1);
+    }
+    // Each kind of peripheral is shared by all the lines mapped to it
+    for kind in kinds {
+        let addresses: Vec<i32> = args
+            .map
+            .iter()
+            .filter(|mapping| mapping.kind == kind)
+            .map(|mapping| mapping.address)
+            .collect();
+        if let Err(e) = simulator.attach_peripheral(&addresses, kind.create()) {
+            eprintln!("Unable to map the {kind} peripheral: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(capacity) = args.history {
+        simulator.enable_history(capacity, 1024);
+    }
+
+    let initial = args.diff_run.then(|| simulator.fork());
+    if args.heatmap || args.heatmap_svg.is_some() {
+        simulator.enable_access_counting();
+    }
+
+    if let Some(address) = args.gdb {
+        if let Err(e) = ssem::gdb::serve(&mut simulator, &address) {
+            eprintln!("GDB server error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.detect_loops {
+        simulator.enable_loop_detection();
+    }
+
+    if args.narrate {
+        simulator.add_observer(Box::new(Narrator::new(BufWriter::new(io::stdout()))));
+    }
+
+    if args.trace {
+        let output: Box<dyn Write> = match &args.trace_file {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!("Unable to create '{}': {e}", path.display());
+                    std::process::exit(1);
+                }
+            },
+            None => Box::new(BufWriter::new(io::stdout())),
+        };
+        let filter = TraceFilter {
+            addresses: args.trace_addresses.map(|s| s.0),
+            opcodes: args.trace_opcodes.map(|s| s.0),
+            cycles: args.trace_cycles.map(|s| s.0),
+        };
+        simulator.add_observer(Box::new(TraceWriter::new(
+            output,
+            args.trace_format,
+            filter,
+        )));
+    }
+
+    let mut timing = args.timing;
+    if let Some(beats) = args.beats_per_instruction {
+        timing.beats_per_instruction = beats;
+    }
+    if let Some(beat_time) = args.beat_time {
+        if !beat_time.is_finite() || beat_time <= 0.0 {
+            eprintln!("The beat time must be a positive number of microseconds");
+            std::process::exit(1);
+        }
+        timing.beat_duration = Duration::from_secs_f64(beat_time / 1_000_000.0);
+    }
+
+    if let Some(path) = &args.vcd {
+        let options = VcdOptions {
+            lines: args.vcd_lines.iter().flat_map(|s| s.0.clone()).collect(),
+            cycles: args.vcd_cycles.0,
+            timing: args.vcd_historic_time.then_some(timing),
+        };
+        let writer = File::create(path).and_then(|f| VcdWriter::new(BufWriter::new(f), options));
+        match writer {
+            Ok(writer) => simulator.add_observer(Box::new(writer)),
+            Err(e) => {
+                eprintln!("Unable to create '{}': {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = &args.frames {
+        let options = FrameOptions {
+            interval: args.frame_every,
+            cycles: args.frame_cycles.0.clone(),
+            pixel_size: args.frame_pixel_size,
+            style: args.frame_style,
+            timing,
+        };
+        match FrameExporter::new(path, options) {
+            Ok(exporter) => simulator.add_observer(Box::new(exporter)),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Ctrl-C stops the run cleanly and SIGUSR1 reports its progress
+    let interrupt = Interrupt::new();
+    if let Err(e) = interrupt.register_signals() {
+        eprintln!("Unable to handle signals: {e}");
+    }
+    if let Some(timeout) = args.timeout {
+        interrupt.stop_after(timeout.0);
+    }
+    simulator.set_interrupt(Some(interrupt.clone()));
 
-    let mut simulator = Simulator::from_file(&Path::new(&args.file));
-    use std::time::Instant;
     let start_time = Instant::now();
 
-    let cycles = simulator.run(args.max_cycles);
+    let ips = match (args.ips, args.realtime) {
+        (Some(ips), _) => Some(ips),
+        (None, true) => Some(timing.instructions_per_second()),
+        (None, false) => None,
+    }
+    .map(|ips| ips * args.speed);
+
+    let fault_options = FaultOptions {
+        seed: args.fault_seed,
+        rate: args.fault_rate.unwrap_or_default(),
+        schedule: args.fault,
+        charge_loss: args.charge_loss.unwrap_or_default(),
+        read_around_ratio: args.read_around_ratio,
+    };
+    let mut faults = None;
+
+    let live = args.live || args.live_input.is_some();
 
-    println!("Run completed!");
+    let (cycles, pacing) = match ips {
+        Some(ips) if !ips.is_finite() || ips <= 0.0 => {
+            eprintln!("The speed must be a positive number of instructions per second");
+            std::process::exit(1);
+        }
+        _ if args.tui => {
+            // The run is reported as any other once the terminal interface is left
+            let start = simulator.cycles;
+            let panel = FrontPanel::new(simulator, args.max_cycles);
+            match run_tui(Tui::new(
+                panel,
+                ips,
+                args.tubes.unwrap_or(Glyphs::Dots),
+                args.green,
+            )) {
+                Ok(panel) => simulator = panel.simulator,
+                Err(e) => {
+                    eprintln!("Terminal error: {e}");
+                    std::process::exit(1);
+                }
+            }
+            ((simulator.cycles - start) as u32, None)
+        }
+        _ if live || !args.poke.is_empty() => {
+            let commands = live.then(|| read_commands(args.live_input));
+            run_live(
+                &mut simulator,
+                args.max_cycles,
+                ips,
+                args.poke,
+                commands,
+                &mut io::stdout(),
+            )
+        }
+        Some(ips) => {
+            let report = run_paced(&mut simulator, args.max_cycles, ips);
+            (report.cycles as u32, Some(report))
+        }
+        None if !fault_options.is_empty() => {
+            let report = run_with_faults(&mut simulator, args.max_cycles, fault_options);
+            let cycles = report.cycles as u32;
+            faults = Some(report);
+            (cycles, None)
+        }
+        None => (simulator.run(args.max_cycles), None),
+    };
+    // Flushes the trace and waveform files before displaying the final state
+    drop(simulator.take_observers());
+    drop(simulator.detach_peripherals());
+
+    match simulator.stop_reason() {
+        Some(reason) => println!("Run completed: {reason}"),
+        None => println!("Run completed!"),
+    }
+    if interrupt.timed_out() {
+        if let Some(timeout) = args.timeout {
+            println!("The run timed out after {timeout}");
+        }
+    }
+    if let Some(StopReason::Loop { period, .. }) = simulator.stop_reason() {
+        // Display programs loop forever, their animation is repeated with the same period
+        println!(
+            "The machine repeats itself every {} on the Baby ({:.0} ips)",
+            HumanDuration(timing.duration(period)),
+            timing.instructions_per_second(),
+        );
+    }
+    if simulator.overflow_count() > 0 {
+        println!("Arithmetic overflows: {}", simulator.overflow_count());
+        for overflow in simulator.overflows() {
+            println!("    {overflow}");
+        }
+        let unlisted = simulator.overflow_count() - simulator.overflows().len() as u64;
+        if unlisted > 0 {
+            println!("    ... and {unlisted} more");
+        }
+    }
+    if !simulator.uninitialized_reads().is_empty() {
+        println!("Reads of words never set:");
+        for read in simulator.uninitialized_reads() {
+            println!("    {read}");
+        }
+    }
+    if let Some(report) = faults {
+        println!("{report}");
+    }
     println!("The final state of the machine is:");
-    println!("{simulator}");
+    match args.display {
+        Some(columns) => {
+            let annotations = annotations(&file, &source_map).unwrap_or_default();
+            let formatter = WordFormatter::new(columns).with_annotations(annotations);
+            println!("{}", formatter.simulator(&simulator));
+        }
+        None => println!("{simulator}"),
+    }
+    if let Some(glyphs) = args.tubes {
+        println!(
+            "{}",
+            TubeRenderer::new(glyphs, args.green).tubes(&simulator)
+        );
+    }
+    if let Some(counts) = simulator.access_counts() {
+        let heatmap =
+            Heatmap::new(counts, args.heatmap_scale).with_color(io::stdout().is_terminal());
+        if args.heatmap {
+            println!("Accesses to the store lines:");
+            println!("{heatmap}");
+        }
+        if let Some(path) = &args.heatmap_svg {
+            let result = File::create(path).and_then(|f| heatmap.write_svg(&mut BufWriter::new(f)));
+            match result {
+                Ok(()) => println!("Heatmap saved to '{}'", path.display()),
+                Err(e) => eprintln!("Unable to save the heatmap to '{}': {e}", path.display()),
+            }
+        }
+    }
+    if let Some(initial) = initial {
+        let diff = StoreDiff::new(&initial.store, &simulator.store)
+            .with_annotations(annotations(&file, &source_map).unwrap_or_default());
+        println!("Words changed by the run:");
+        print_diff(&diff, "The run changed no words");
+    }
+    if let Some(path) = &args.snapshot {
+        match simulator.save_snapshot(path) {
+            Ok(()) => println!("Snapshot saved to '{}'", path.display()),
+            Err(e) => eprintln!("Unable to save the snapshot to '{}': {e}", path.display()),
+        }
+    }
     println!(
         "{} cycles executed in {:.2?} ({:.0?} cps)",
         cycles,
         start_time.elapsed(),
         f64::from(cycles) / start_time.elapsed().as_secs_f64(),
     );
+    if let Some(report) = pacing {
+        println!("{report}");
+    }
+    println!(
+        "The Baby would have taken {} ({timing})",
+        HumanDuration(timing.duration(u64::from(cycles))),
+    );
+}
+
+/// Lists the words that differ, highlighting the changed bits on terminals
+fn print_diff(diff: &StoreDiff, identical: &str) {
+    if diff.is_empty() {
+        println!("{identical}");
+        return;
+    }
+    println!("{}", diff.side_by_side(io::stdout().is_terminal()));
+    match diff.changes.len() {
+        1 => println!("1 word differs"),
+        count => println!("{count} words differ"),
+    }
+}
+
+/// Converts an image into a block of words, see `--import-image`
+fn import_image(path: &Path, args: &Args) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&args.threshold) {
+        return Err("the threshold must be between 0 and 1".to_string());
+    }
+    let options = ImportOptions {
+        threshold: args.threshold,
+        dithering: args.dither,
+        invert: args.invert,
+    };
+    let image = Image::from_file(path).map_err(|e| e.to_string())?;
+    let words = image.to_words(&options)?;
+    match &args.import_output {
+        Some(output) => {
+            let format = match output.extension().and_then(|e| e.to_str()) {
+                Some("snp") => BlockFormat::Snp,
+                Some("asm") => BlockFormat::Asm,
+                _ => {
+                    return Err(format!(
+                        "unknown format of '{}', expected .asm or .snp",
+                        output.display()
+                    ))
+                }
+            };
+            let mut file = BufWriter::new(File::create(output).map_err(|e| e.to_string())?);
+            write_block(&mut file, &words, args.import_address, format)
+        }
+        None => write_block(
+            &mut io::stdout().lock(),
+            &words,
+            args.import_address,
+            args.import_format,
+        ),
+    }
 }
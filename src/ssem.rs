@@ -1,8 +1,36 @@
 //! A simulator for the Small-Scale Experimental Machine
 
-pub(self) mod opcode;
+pub mod crt;
+pub mod dap;
+pub mod diff;
+pub mod display;
+pub mod faults;
+pub mod frames;
+pub mod gdb;
+pub mod heatmap;
+mod history;
+pub mod image;
+pub mod interrupt;
+pub mod live;
+mod loop_detector;
+pub mod narrator;
+mod opcode;
+pub mod overflow;
+pub mod panel;
+pub mod peripheral;
+pub mod profile;
+mod random;
 pub mod simulator;
-pub(self) mod store;
+mod store;
+pub mod teleprinter;
+#[cfg(test)]
+mod testing;
+pub mod throttle;
+pub mod timing;
+pub mod trace;
+pub mod tui;
+pub mod uninitialized;
+pub mod vcd;
 
 mod tests {
     // TODO: move these tests into tests/ folder
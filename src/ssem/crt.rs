@@ -0,0 +1,190 @@
+//! Terminal rendering of the three cathode ray tubes of the machine: store, accumulator and control
+//!
+//! Like the monitors of the Baby, the tubes show bits least significant first from left to right, a 1 being a dash
+//! and a 0 a dot.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::simulator::Simulator;
+use super::store::Store;
+
+/// Characters the spots are drawn with
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Glyphs {
+    /// Half blocks, two lines of the tube per row of text. Spots are about square in most terminals.
+    #[default]
+    Blocks,
+
+    /// Braille patterns, 2×4 spots per character. The most compact.
+    Braille,
+
+    /// A dash for each 1 and a dot for each 0, as the tubes showed them
+    Dots,
+}
+
+impl FromStr for Glyphs {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Glyphs, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "blocks" => Ok(Glyphs::Blocks),
+            "braille" => Ok(Glyphs::Braille),
+            "dots" => Ok(Glyphs::Dots),
+            _ => Err(format!(
+                "unknown glyphs '{input}', expected blocks, braille or dots"
+            )),
+        }
+    }
+}
+
+/// ANSI escape sequences of the phosphor-green theme
+const GREEN: &str = "\x1b[92;40m";
+const RESET: &str = "\x1b[0m";
+
+/// Draws the tubes as text
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TubeRenderer {
+    pub glyphs: Glyphs,
+
+    /// Draw green spots on black with ANSI escape sequences
+    pub green: bool,
+}
+
+impl TubeRenderer {
+    pub fn new(glyphs: Glyphs, green: bool) -> TubeRenderer {
+        TubeRenderer { glyphs, green }
+    }
+
+    /// Lines of text picturing the given words, one word per line of the tube
+    pub fn lines(&self, words: &[i32]) -> Vec<String> {
+        let bits: Vec<Vec<bool>> = words.iter().map(|w| Store::bits(*w).collect()).collect();
+        let spot = |x: usize, y: usize| bits.get(y).is_some_and(|line| line[x]);
+
+        let lines: Vec<String> = match self.glyphs {
+            Glyphs::Dots => bits
+                .iter()
+                .map(|line| line.iter().map(|b| if *b { '-' } else { '·' }).collect())
+                .collect(),
+            Glyphs::Blocks => (0..bits.len())
+                .step_by(2)
+                .map(|y| {
+                    (0..32)
+                        .map(|x| match (spot(x, y), spot(x, y + 1)) {
+                            (true, true) => '█',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (false, false) => ' ',
+                        })
+                        .collect()
+                })
+                .collect(),
+            Glyphs::Braille => (0..bits.len())
+                .step_by(4)
+                .map(|y| {
+                    (0..32)
+                        .step_by(2)
+                        .map(|x| {
+                            // Dot numbering of the braille patterns, column by column then the bottom row
+                            const DOTS: [(usize, usize, u32); 8] = [
+                                (0, 0, 0x01),
+                                (0, 1, 0x02),
+                                (0, 2, 0x04),
+                                (1, 0, 0x08),
+                                (1, 1, 0x10),
+                                (1, 2, 0x20),
+                                (0, 3, 0x40),
+                                (1, 3, 0x80),
+                            ];
+                            let pattern = DOTS
+                                .iter()
+                                .filter(|(dx, dy, _)| spot(x + dx, y + dy))
+                                .fold(0, |pattern, (_, _, dot)| pattern | dot);
+                            char::from_u32(0x2800 + pattern).unwrap_or(' ')
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+
+        if self.green {
+            lines
+                .into_iter()
+                .map(|line| format!("{GREEN}{line}{RESET}"))
+                .collect()
+        } else {
+            lines
+        }
+    }
+
+    /// A tube with its title, framed
+    pub fn tube(&self, title: &str, words: &[i32]) -> String {
+        let lines = self.lines(words);
+        let width = match self.glyphs {
+            Glyphs::Braille => 16,
+            _ => 32,
+        };
+        let mut tube = format!("┌{title:─^width$}┐\n");
+        for line in lines {
+            tube += &format!("│{line}│\n");
+        }
+        tube += &format!("└{}┘\n", "─".repeat(width));
+        tube
+    }
+
+    /// The store, accumulator and control tubes of a machine. The control tube shows CI above the present
+    /// instruction.
+    pub fn tubes<'a>(&'a self, simulator: &'a Simulator) -> Tubes<'a> {
+        Tubes(self, simulator)
+    }
+}
+
+/// Displays the tubes of a machine, see `TubeRenderer::tubes`
+pub struct Tubes<'a>(&'a TubeRenderer, &'a Simulator);
+
+impl fmt::Display for Tubes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (renderer, simulator) = (self.0, self.1);
+        let present = simulator.store[simulator.ci.rem_euclid(simulator.store.size)];
+        write!(f, "{}", renderer.tube("Store", &simulator.store.words))?;
+        write!(f, "{}", renderer.tube("Accumulator", &[simulator.a]))?;
+        write!(f, "{}", renderer.tube("Control", &[simulator.ci, present]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::ssem::simulator::Simulator;
+
+    use super::{Glyphs, TubeRenderer};
+
+    #[test]
+    fn glyphs() {
+        // 1, 2 and 3 are drawn from the left as the machine writes the least significant bit first
+        let words = [1, 2, 3];
+        let render = |glyphs| TubeRenderer::new(glyphs, false).lines(&words);
+
+        assert_eq!(render(Glyphs::Dots)[1], format!("·-{}", "·".repeat(30)));
+        let blocks = render(Glyphs::Blocks);
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[0].starts_with("▀▄ "));
+        assert!(blocks[1].starts_with("▀▀ "));
+        let braille = render(Glyphs::Braille);
+        assert_eq!(braille.len(), 1);
+        assert!(braille[0].starts_with("\u{2835}\u{2800}"));
+    }
+
+    #[test]
+    fn tubes() {
+        let simulator = Simulator::from_file(Path::new("samples/ssem/nightmare.snp"));
+        let renderer = TubeRenderer::new(Glyphs::Blocks, true);
+        let text = renderer.tubes(&simulator).to_string();
+
+        // 16 rows for the store, 1 for the accumulator and 1 for the control tube, plus the frames
+        assert_eq!(text.lines().count(), 16 + 1 + 1 + 3 * 2);
+        assert!(text.contains("\x1b[92;40m"));
+        assert!(text.starts_with(&format!("┌{}Store{}┐", "─".repeat(13), "─".repeat(14))));
+    }
+}
@@ -0,0 +1,612 @@
+//! Debug Adapter Protocol server
+//!
+//! Allows stepping through `.asm` and `.snp` sources from editors such as VS Code. Messages are exchanged on the
+//! standard input and output, each one being a JSON object preceded by a `Content-Length` header.
+//!
+//! The program to debug is given by the `program` argument of the `launch` request.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::panic;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use super::profile::Profile;
+use super::simulator::{Simulator, StopReason};
+use super::store::SourceMap;
+
+/// Amount of cycles run between two checks for incoming requests
+const CONTINUE_BATCH_CYCLES: u32 = 10_000;
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_REFERENCE: i64 = 1;
+const STORE_REFERENCE: i64 = 2;
+
+/// Serves a debugging session on the standard input and output.
+///
+/// # Arguments
+///
+/// * `history` - Amount of cycles to record to allow stepping back, if any
+/// * `profile` - Behaviour of the machine, unless the launch request gives one
+pub fn serve(history: Option<usize>, profile: Profile) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    // Requests are read on their own thread so that a running program can be paused
+    thread::spawn(move || {
+        let mut input = BufReader::new(io::stdin());
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    DapServer::new(io::stdout(), history, profile).serve(receiver)
+}
+
+/// Reads a message in the base protocol format. Returns `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length header",
+        ));
+    };
+    let mut content = vec![0u8; length];
+    input.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The program being debugged
+struct Session {
+    simulator: Simulator,
+    source_map: SourceMap,
+    source_path: String,
+}
+
+pub struct DapServer<W: Write> {
+    output: W,
+
+    /// Sequence number of the next message sent
+    seq: i64,
+
+    history: Option<usize>,
+    profile: Profile,
+    session: Option<Session>,
+
+    /// Set while the program runs after a `continue` request
+    running: bool,
+    stop_on_entry: bool,
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(output: W, history: Option<usize>, profile: Profile) -> DapServer<W> {
+        DapServer {
+            output,
+            seq: 1,
+            history,
+            profile,
+            session: None,
+            running: false,
+            stop_on_entry: false,
+        }
+    }
+
+    /// Handles requests until the client disconnects
+    pub fn serve(&mut self, requests: Receiver<Value>) -> io::Result<()> {
+        loop {
+            let request = if self.running {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+
+            if let Some(request) = request {
+                if !self.handle(&request)? {
+                    return Ok(());
+                }
+            }
+            if self.running {
+                self.run_batch()?;
+            }
+        }
+    }
+
+    /// Handles a single request. Returns `false` when the session is over.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+
+        let body = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsStepBack": self.history.is_some(),
+            })),
+            "launch" => {
+                let body = self.launch(arguments);
+                let launched = body.is_ok();
+                self.respond(request, body)?;
+                // The client only sends breakpoints once the program is launched
+                if launched {
+                    self.send_event("initialized", json!({}))?;
+                }
+                return Ok(true);
+            }
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})))?;
+                if self.stop_on_entry {
+                    self.send_stopped("entry", None)?;
+                } else {
+                    self.running = true;
+                }
+                return Ok(true);
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "SSEM" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Store", "variablesReference": STORE_REFERENCE, "expensive": false },
+            ]})),
+            "variables" => self.variables(arguments["variablesReference"].as_i64()),
+            "continue" => {
+                self.respond(request, Ok(json!({ "allThreadsContinued": true })))?;
+                self.running = true;
+                return Ok(true);
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.respond(request, Ok(json!({})))?;
+                return self.step().map(|_| true);
+            }
+            "stepBack" | "reverseContinue" => {
+                self.respond(request, Ok(json!({})))?;
+                return self.reverse(command == "reverseContinue").map(|_| true);
+            }
+            "pause" => {
+                self.respond(request, Ok(json!({})))?;
+                self.running = false;
+                self.send_stopped("pause", None)?;
+                return Ok(true);
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})))?;
+                self.send_event("terminated", json!({}))?;
+                return Ok(false);
+            }
+            _ => Err(format!("Unsupported request '{command}'")),
+        };
+
+        self.respond(request, body)?;
+        Ok(true)
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let Some(program) = arguments["program"].as_str() else {
+            return Err("Missing 'program' argument".into());
+        };
+        if !Path::new(program).is_file() {
+            return Err(format!("Unable to find '{program}'"));
+        }
+
+        let profile = match arguments["profile"].as_str() {
+            Some(name) => name.parse::<Profile>()?,
+            None => self.profile,
+        };
+
+        // Loaders panic on invalid files, the message is still displayed on the standard error
+        let path = program.to_string();
+        let (mut simulator, source_map) =
+            panic::catch_unwind(|| Simulator::from_file_mapped(Path::new(&path)))
+                .map_err(|_| format!("Unable to load '{program}'"))?;
+        simulator.set_profile(profile);
+        if let Some(capacity) = self.history {
+            simulator.enable_history(capacity, 1024);
+        }
+
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.session = Some(Session {
+            simulator,
+            source_map,
+            source_path: program.to_string(),
+        });
+        Ok(json!({}))
+    }
+
+    /// Replaces every breakpoint with the given source lines
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let session = self.session.as_mut().ok_or("No program launched")?;
+        session.simulator.breakpoints.clear();
+
+        let lines = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|b| {
+                let line = b["line"].as_u64().unwrap_or_default() as usize;
+                match session.source_map.address(line) {
+                    Some(address) => {
+                        session.simulator.breakpoints.insert(address);
+                        json!({ "verified": true, "line": line })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "No instruction on this line",
+                    }),
+                }
+            })
+            .collect();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// The SSEM has no call stack: the only frame is the next instruction
+    fn stack_trace(&self) -> Result<Value, String> {
+        let session = self.session.as_ref().ok_or("No program launched")?;
+        let address = session.simulator.next_address();
+        let line = session.source_map.line(address).unwrap_or(0);
+        let source_name = Path::new(&session.source_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(json!({
+            "stackFrames": [{
+                "id": 1,
+                "name": format!("{:02} {}", address, decode(&session.simulator, address)),
+                "source": { "name": source_name, "path": session.source_path },
+                "line": line,
+                "column": 1,
+            }],
+            "totalFrames": 1,
+        }))
+    }
+
+    fn variables(&self, reference: Option<i64>) -> Result<Value, String> {
+        let session = self.session.as_ref().ok_or("No program launched")?;
+        let simulator = &session.simulator;
+
+        let variables: Vec<Value> = match reference {
+            Some(REGISTERS_REFERENCE) => vec![
+                json!({ "name": "A", "value": simulator.a.to_string(), "variablesReference": 0 }),
+                json!({ "name": "CI", "value": simulator.ci.to_string(), "variablesReference": 0 }),
+            ],
+            Some(STORE_REFERENCE) => (0..simulator.store.size)
+                .map(|address| {
+                    json!({
+                        "name": format!("{address:02}"),
+                        "value": format!(
+                            "{} ({})",
+                            simulator.store[address],
+                            decode(simulator, address),
+                        ),
+                        "variablesReference": 0,
+                    })
+                })
+                .collect(),
+            _ => return Err("Unknown variables reference".into()),
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn step(&mut self) -> io::Result<()> {
+        let Some(session) = self.session.as_mut() else {
+            return Ok(());
+        };
+        match session.simulator.step() {
+            Some(StopReason::Halted) => self.send_exited(),
+            _ => self.send_stopped("step", None),
+        }
+    }
+
+    fn reverse(&mut self, to_breakpoint: bool) -> io::Result<()> {
+        let Some(session) = self.session.as_mut() else {
+            return Ok(());
+        };
+        if to_breakpoint {
+            match session.simulator.reverse_continue() {
+                Some(StopReason::Breakpoint(_)) => self.send_stopped("breakpoint", None),
+                Some(_) => self.send_stopped("data breakpoint", None),
+                None => self.send_stopped("entry", Some("Beginning of the history")),
+            }
+        } else {
+            session.simulator.step_back(1);
+            self.send_stopped("step", None)
+        }
+    }
+
+    /// Runs the program for a while after a `continue` request, reporting when it stops
+    fn run_batch(&mut self) -> io::Result<()> {
+        let Some(session) = self.session.as_mut() else {
+            self.running = false;
+            return Ok(());
+        };
+        session.simulator.run(CONTINUE_BATCH_CYCLES);
+
+        let stopped = match session.simulator.stop_reason() {
+            Some(StopReason::MaxCycles) | None => return Ok(()),
+            Some(StopReason::Halted) => {
+                self.running = false;
+                return self.send_exited();
+            }
+            Some(StopReason::Breakpoint(_)) => ("breakpoint", None),
+            Some(StopReason::Watchpoint(_)) => ("data breakpoint", None),
+            Some(StopReason::InvalidInstruction(_)) => ("exception", Some("Invalid instruction")),
+            Some(StopReason::Loop { .. }) => ("pause", Some("Infinite loop")),
+            Some(StopReason::Overflow(_)) => ("exception", Some("Arithmetic overflow")),
+            Some(StopReason::Interrupted) => ("pause", Some("Interrupted")),
+        };
+        self.running = false;
+        self.send_stopped(stopped.0, stopped.1)
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        self.send(response)
+    }
+
+    fn send_stopped(&mut self, reason: &str, description: Option<&str>) -> io::Result<()> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(description) = description {
+            body["description"] = Value::String(description.into());
+        }
+        self.send_event("stopped", body)
+    }
+
+    /// Reports that the program reached STP. It can't be resumed, the session is over.
+    fn send_exited(&mut self) -> io::Result<()> {
+        self.send_event("exited", json!({ "exitCode": 0 }))?;
+        self.send_event("terminated", json!({}))
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let content = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )?;
+        self.output.flush()
+    }
+}
+
+/// Instruction held at the given address, as it would be written in an assembly file
+fn decode(simulator: &Simulator, address: i32) -> String {
+    match simulator.store.decode_instruction(address) {
+        Ok((opcode, data)) => format!("{opcode} {data}"),
+        Err(message) => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use crate::ssem::testing::TempDir;
+
+    use super::{read_message, DapServer, Profile};
+
+    /// Extracts the messages sent by the server
+    fn messages(output: &[u8]) -> Vec<Value> {
+        let mut input = Cursor::new(output);
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut input).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn request(server: &mut DapServer<Vec<u8>>, command: &str, arguments: Value) -> Vec<Value> {
+        server.output.clear();
+        server
+            .handle(
+                &json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments }),
+            )
+            .unwrap();
+        while server.running {
+            server.run_batch().unwrap();
+        }
+        messages(&server.output)
+    }
+
+    #[test]
+    fn session() {
+        let mut server = DapServer::new(Vec::new(), None, Profile::default());
+        request(&mut server, "initialize", json!({}));
+
+        let replies = request(
+            &mut server,
+            "launch",
+            json!({ "program": "samples/ssem/fibonacci.asm" }),
+        );
+        assert_eq!(replies[0]["success"], true);
+        assert_eq!(replies[1]["event"], "initialized");
+
+        // Line 16 holds address 10, line 3 is a comment
+        let replies = request(
+            &mut server,
+            "setBreakpoints",
+            json!({ "source": { "path": "samples/ssem/fibonacci.asm" }, "breakpoints": [{ "line": 16 }, { "line": 3 }] }),
+        );
+        assert_eq!(replies[0]["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(replies[0]["body"]["breakpoints"][1]["verified"], false);
+
+        let replies = request(&mut server, "configurationDone", json!({}));
+        assert_eq!(replies[1]["body"]["reason"], "breakpoint");
+
+        let replies = request(&mut server, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(replies[0]["body"]["stackFrames"][0]["line"], 16);
+        assert_eq!(replies[0]["body"]["stackFrames"][0]["name"], "10 SUB 28");
+
+        request(&mut server, "next", json!({ "threadId": 1 }));
+        let replies = request(&mut server, "variables", json!({ "variablesReference": 1 }));
+        assert_eq!(replies[0]["body"]["variables"][0]["value"], "-1");
+        assert_eq!(replies[0]["body"]["variables"][1]["value"], "10");
+
+        // Running to STP ends the session
+        request(
+            &mut server,
+            "setBreakpoints",
+            json!({ "source": { "path": "samples/ssem/fibonacci.asm" }, "breakpoints": [] }),
+        );
+        let replies = request(&mut server, "continue", json!({ "threadId": 1 }));
+        assert_eq!(replies[1]["event"], "exited");
+        assert_eq!(replies[1]["body"]["exitCode"], 0);
+        assert_eq!(replies[2]["event"], "terminated");
+    }
+
+    /// Launches the given program, stopped on its first instruction
+    fn launch(server: &mut DapServer<Vec<u8>>, program: &str) {
+        request(server, "initialize", json!({}));
+        request(
+            server,
+            "launch",
+            json!({ "program": program, "stopOnEntry": true }),
+        );
+        let replies = request(server, "configurationDone", json!({}));
+        assert_eq!(replies[1]["body"]["reason"], "entry");
+    }
+
+    fn line(server: &mut DapServer<Vec<u8>>) -> Value {
+        request(server, "stackTrace", json!({ "threadId": 1 }))[0]["body"]["stackFrames"][0]["line"]
+            .clone()
+    }
+
+    #[test]
+    fn set_breakpoints() {
+        let mut server = DapServer::new(Vec::new(), None, Profile::default());
+        let arguments = json!({ "source": { "path": "samples/ssem/fibonacci.asm" }, "breakpoints": [{ "line": 16 }] });
+        let replies = request(&mut server, "setBreakpoints", arguments.clone());
+        assert_eq!(replies[0]["success"], false);
+        assert_eq!(replies[0]["message"], "No program launched");
+
+        launch(&mut server, "samples/ssem/fibonacci.asm");
+        let replies = request(&mut server, "setBreakpoints", arguments);
+        assert_eq!(
+            replies[0]["body"]["breakpoints"],
+            json!([{ "verified": true, "line": 16 }])
+        );
+
+        // Each request replaces the breakpoints of the previous one. Line 8 holds address 2.
+        let replies = request(
+            &mut server,
+            "setBreakpoints",
+            json!({ "source": { "path": "samples/ssem/fibonacci.asm" }, "breakpoints": [{ "line": 8 }] }),
+        );
+        assert_eq!(replies[0]["body"]["breakpoints"][0]["line"], 8);
+        let breakpoints = &server.session.as_ref().unwrap().simulator.breakpoints;
+        assert_eq!(breakpoints.iter().collect::<Vec<_>>(), [&2]);
+
+        let replies = request(&mut server, "continue", json!({ "threadId": 1 }));
+        assert_eq!(replies[0]["success"], true);
+        assert_eq!(replies[1]["body"]["reason"], "breakpoint");
+        assert_eq!(line(&mut server), 8);
+    }
+
+    #[test]
+    fn stepping() {
+        let mut server = DapServer::new(Vec::new(), Some(1000), Profile::default());
+        let replies = request(&mut server, "initialize", json!({}));
+        assert_eq!(replies[0]["body"]["supportsStepBack"], true);
+        launch(&mut server, "samples/ssem/fibonacci.asm");
+        assert_eq!(line(&mut server), 7);
+
+        for expected in [8, 9, 10] {
+            let replies = request(&mut server, "next", json!({ "threadId": 1 }));
+            assert_eq!(replies[0]["success"], true);
+            assert_eq!(replies[1]["event"], "stopped");
+            assert_eq!(replies[1]["body"]["reason"], "step");
+            assert_eq!(line(&mut server), expected);
+        }
+
+        let replies = request(&mut server, "stepBack", json!({ "threadId": 1 }));
+        assert_eq!(replies[0]["success"], true);
+        assert_eq!(replies[1]["body"]["reason"], "step");
+        assert_eq!(line(&mut server), 9);
+        assert_eq!(server.session.as_ref().unwrap().simulator.cycles, 2);
+
+        // Without breakpoints, running backwards stops at the beginning of the history
+        let replies = request(&mut server, "reverseContinue", json!({ "threadId": 1 }));
+        assert_eq!(replies[1]["body"]["reason"], "entry");
+        assert_eq!(
+            replies[1]["body"]["description"],
+            "Beginning of the history"
+        );
+        assert_eq!(line(&mut server), 7);
+    }
+
+    #[test]
+    fn exited() {
+        let dir = TempDir::new();
+        let program = dir.write("stop.asm", "00 NUM 0\n01 STP\n");
+        let mut server = DapServer::new(Vec::new(), None, Profile::default());
+        launch(&mut server, program.to_str().unwrap());
+
+        // Stepping over STP ends the program
+        let replies = request(&mut server, "next", json!({ "threadId": 1 }));
+        assert_eq!(replies[0]["success"], true);
+        assert_eq!(replies[1]["event"], "exited");
+        assert_eq!(replies[1]["body"]["exitCode"], 0);
+        assert_eq!(replies[2]["event"], "terminated");
+
+        // Disconnecting ends the session
+        server.output.clear();
+        let request =
+            json!({ "seq": 2, "type": "request", "command": "disconnect", "arguments": {} });
+        assert!(!server.handle(&request).unwrap());
+        let replies = messages(&server.output);
+        assert_eq!(replies[0]["command"], "disconnect");
+        assert_eq!(replies[1]["event"], "terminated");
+    }
+}
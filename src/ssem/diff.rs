@@ -0,0 +1,170 @@
+//! Comparison of two stores, e.g. a program before and after its run, or two snapshots
+
+use std::fmt;
+use std::path::Path;
+
+use super::display::{annotations, instruction};
+use super::store::Store;
+
+/// ANSI escape sequences of the bits that changed, cleared in red and set in green
+const CLEARED: &str = "\x1b[1;31m";
+const SET: &str = "\x1b[1;32m";
+const RESET: &str = "\x1b[0m";
+
+/// A word that differs between the two stores
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WordChange {
+    pub address: i32,
+    pub old: i32,
+    pub new: i32,
+}
+
+/// Words that differ between two stores, in address order.
+///
+/// Its `Display` implementation lists them side by side, see `side_by_side` for colors.
+#[derive(Debug, Clone, Default)]
+pub struct StoreDiff {
+    pub changes: Vec<WordChange>,
+
+    /// Annotation of each address, see `display::annotations`
+    pub annotations: Vec<String>,
+}
+
+impl StoreDiff {
+    /// Compares two stores. Words beyond the end of the smaller store count as 0.
+    pub fn new(old: &Store, new: &Store) -> StoreDiff {
+        let size = old.words.len().max(new.words.len());
+        let word = |store: &Store, address: usize| store.words.get(address).copied().unwrap_or(0);
+        let changes = (0..size)
+            .map(|address| WordChange {
+                address: address as i32,
+                old: word(old, address),
+                new: word(new, address),
+            })
+            .filter(|change| change.old != change.new)
+            .collect();
+        StoreDiff {
+            changes,
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Compares the stores loaded from two .asm, .snp or .tp files, annotated with the comments of the first one
+    pub fn from_files(old: &Path, new: &Path) -> StoreDiff {
+        let (old_store, source_map) = Store::from_file_mapped(old);
+        let (new_store, _) = Store::from_file_mapped(new);
+        StoreDiff::new(&old_store, &new_store)
+            .with_annotations(annotations(old, &source_map).unwrap_or_default())
+    }
+
+    pub fn with_annotations(mut self, annotations: Vec<String>) -> StoreDiff {
+        self.annotations = annotations;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Before and after columns of each changed word: bits, decimal and decoded instruction. With `color`, the bits
+    /// that changed are highlighted with ANSI escape sequences.
+    pub fn side_by_side(&self, color: bool) -> SideBySide<'_> {
+        SideBySide(self, color)
+    }
+}
+
+impl fmt::Display for StoreDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.side_by_side(false))
+    }
+}
+
+/// Displays a diff, see `StoreDiff::side_by_side`
+pub struct SideBySide<'a>(&'a StoreDiff, bool);
+
+impl SideBySide<'_> {
+    /// Bits least significant first, the ones differing from `other` in `highlight`
+    fn bits(&self, word: i32, other: i32, highlight: &str) -> String {
+        let mut text = String::new();
+        for (bit, changed) in Store::bits(word).zip(Store::bits(word ^ other)) {
+            let digit = if bit { '1' } else { '0' };
+            if changed && self.1 {
+                text += &format!("{highlight}{digit}{RESET}");
+            } else {
+                text.push(digit);
+            }
+        }
+        text
+    }
+}
+
+impl fmt::Display for SideBySide<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let diff = self.0;
+        writeln!(f, "     {:<54}│ After", "Before")?;
+        for change in &diff.changes {
+            let annotation = diff
+                .annotations
+                .get(change.address as usize)
+                .map_or("", String::as_str);
+            let line = format!(
+                " {:02}  {} {:11}  {:<7} │ {} {:11}  {:<7}  {annotation}",
+                change.address,
+                self.bits(change.old, change.new, CLEARED),
+                change.old,
+                instruction(change.old),
+                self.bits(change.new, change.old, SET),
+                change.new,
+                instruction(change.new),
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::ssem::simulator::Simulator;
+
+    use super::StoreDiff;
+
+    #[test]
+    fn run() {
+        let path = Path::new("samples/ssem/fibonacci.asm");
+        let (mut simulator, _) = Simulator::from_file_mapped(path);
+        let initial = simulator.fork();
+        simulator.run(10_000);
+
+        let diff = StoreDiff::new(&initial.store, &simulator.store);
+        assert!(!diff.is_empty());
+        assert!(diff.changes.iter().all(|change| change.old != change.new));
+        assert!(StoreDiff::new(&initial.store, &initial.store).is_empty());
+    }
+
+    #[test]
+    fn files() {
+        let diff = StoreDiff::from_files(
+            Path::new("samples/ssem/fibonacci.asm"),
+            Path::new("samples/ssem/fibonacci.tp"),
+        );
+        assert!(diff.is_empty());
+
+        let diff = StoreDiff::from_files(
+            Path::new("samples/ssem/fibonacci.asm"),
+            Path::new("samples/ssem/nightmare.snp"),
+        );
+        let text = diff.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), diff.changes.len() + 1);
+        assert!(lines[0].contains("Before") && lines[0].contains("After"));
+        // fibonacci.asm annotates its line 1
+        let line = lines.iter().find(|line| line.starts_with(" 01 ")).unwrap();
+        assert!(line.contains("LDN 31") && line.ends_with("Load negative of counter"));
+
+        let colored = diff.side_by_side(true).to_string();
+        assert!(colored.contains("\x1b[1;31m") && colored.contains("\x1b[1;32m"));
+    }
+}
@@ -0,0 +1,256 @@
+//! Listings of the store and registers, with a choice of columns to read the words by
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::opcode::Opcode;
+use super::simulator::Simulator;
+use super::store::{SourceMap, Store};
+use super::teleprinter;
+
+/// A way of showing a word
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Column {
+    /// Binary, least significant bit first, as on the machine
+    Ssem,
+
+    /// Binary, most significant bit first
+    Modern,
+
+    /// Signed decimal
+    Decimal,
+
+    /// Hexadecimal of the two's complement
+    Hex,
+
+    /// Base 32 in Manchester teleprinter code, as in period listings
+    Teleprinter,
+
+    /// Word decoded as an instruction, e.g. `LDN 27`
+    Instruction,
+
+    /// Comment of the source line the word was loaded from
+    Annotation,
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Column, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "ssem" | "lsb" => Ok(Column::Ssem),
+            "modern" | "msb" => Ok(Column::Modern),
+            "decimal" | "dec" => Ok(Column::Decimal),
+            "hex" => Ok(Column::Hex),
+            "teleprinter" | "tp" => Ok(Column::Teleprinter),
+            "instruction" | "asm" => Ok(Column::Instruction),
+            "annotation" | "comment" => Ok(Column::Annotation),
+            _ => Err(format!(
+                "unknown column '{input}', expected ssem, modern, decimal, hex, teleprinter, instruction or annotation"
+            )),
+        }
+    }
+}
+
+/// Columns of a listing, parsed from a comma-separated list such as `ssem,decimal,instruction`, or `all`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns(pub Vec<Column>);
+
+impl FromStr for Columns {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Columns, Self::Err> {
+        if input.trim() == "all" {
+            return Ok(Columns(vec![
+                Column::Ssem,
+                Column::Modern,
+                Column::Decimal,
+                Column::Hex,
+                Column::Teleprinter,
+                Column::Instruction,
+                Column::Annotation,
+            ]));
+        }
+        input
+            .split(',')
+            .map(Column::from_str)
+            .collect::<Result<_, _>>()
+            .map(Columns)
+    }
+}
+
+impl Default for Columns {
+    /// Same as the `Display` implementation of `Store`
+    fn default() -> Self {
+        Columns(vec![Column::Ssem])
+    }
+}
+
+/// Lists words with the chosen columns, one word per line.
+///
+/// `store` and `simulator` give values implementing `Display`, e.g.
+/// `println!("{}", WordFormatter::new(columns).store(&store))`.
+#[derive(Debug, Clone, Default)]
+pub struct WordFormatter {
+    pub columns: Columns,
+
+    /// Annotation of each address, see `annotations`
+    pub annotations: Vec<String>,
+}
+
+impl WordFormatter {
+    pub fn new(columns: Columns) -> WordFormatter {
+        WordFormatter {
+            columns,
+            annotations: Vec::new(),
+        }
+    }
+
+    pub fn with_annotations(mut self, annotations: Vec<String>) -> WordFormatter {
+        self.annotations = annotations;
+        self
+    }
+
+    /// Writes the columns of a word, without line end. Registers have no instruction nor annotation.
+    pub fn write_word(
+        &self,
+        f: &mut impl fmt::Write,
+        word: i32,
+        address: Option<i32>,
+    ) -> fmt::Result {
+        let mut line = String::new();
+        for column in &self.columns.0 {
+            if !line.is_empty() {
+                line.push_str("  ");
+            }
+            match column {
+                Column::Ssem => line += &format!("{:032b}", word.reverse_bits()),
+                Column::Modern => line += &format!("{word:032b}"),
+                Column::Decimal => line += &format!("{word:11}"),
+                Column::Hex => line += &format!("0x{word:08X}"),
+                Column::Teleprinter => line += &teleprinter::format_word(word),
+                Column::Instruction => {
+                    let text = match address {
+                        Some(_) => instruction(word),
+                        None => String::new(),
+                    };
+                    line += &format!("{text:<7}");
+                }
+                Column::Annotation => {
+                    let annotation = address
+                        .and_then(|address| self.annotations.get(address as usize))
+                        .map_or("", String::as_str);
+                    line += annotation;
+                }
+            }
+        }
+        write!(f, "{}", line.trim_end())
+    }
+
+    /// Listing of the whole store
+    pub fn store<'a>(&'a self, store: &'a Store) -> impl fmt::Display + 'a {
+        Listing(move |f: &mut fmt::Formatter| {
+            for (address, word) in store.words.iter().enumerate() {
+                write!(f, " {address:02} ")?;
+                self.write_word(f, *word, Some(address as i32))?;
+                writeln!(f)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Listing of the registers followed by the store, the same layout as the `Display` implementation of
+    /// `Simulator`
+    pub fn simulator<'a>(&'a self, simulator: &'a Simulator) -> impl fmt::Display + 'a {
+        Listing(move |f: &mut fmt::Formatter| {
+            for (label, word) in [("CI", simulator.ci), ("A ", simulator.a)] {
+                write!(f, " {label} ")?;
+                self.write_word(f, word, None)?;
+                writeln!(f)?;
+            }
+            writeln!(f)?;
+            write!(f, "{}", self.store(&simulator.store))
+        })
+    }
+}
+
+/// Displays with a closure
+struct Listing<F: Fn(&mut fmt::Formatter) -> fmt::Result>(F);
+
+impl<F: Fn(&mut fmt::Formatter) -> fmt::Result> fmt::Display for Listing<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (self.0)(f)
+    }
+}
+
+/// Mnemonic and operand of a word decoded as an instruction
+pub fn instruction(word: i32) -> String {
+    match Store::decode_word(word) {
+        Ok((opcode @ (Opcode::CMP | Opcode::STP), _)) => opcode.to_string(),
+        Ok((opcode, operand)) => format!("{opcode} {operand}"),
+        Err(_) => "???".to_string(),
+    }
+}
+
+/// Comments of the source lines each address was loaded from, empty for addresses without one
+pub fn annotations(path: &Path, source_map: &SourceMap) -> io::Result<Vec<String>> {
+    let source = fs::read_to_string(path)?;
+    let lines: Vec<&str> = source.lines().collect();
+    let annotations = (0..Store::new().size)
+        .map(|address| {
+            source_map
+                .line(address)
+                .and_then(|line| lines.get(line - 1))
+                .and_then(|line| line.split_once(';'))
+                .map_or(String::new(), |(_, comment)| comment.trim().to_string())
+        })
+        .collect();
+    Ok(annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use crate::ssem::simulator::Simulator;
+
+    use super::{annotations, instruction, Column, Columns, WordFormatter};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Columns::from_str("ssem,decimal,asm"),
+            Ok(Columns(vec![
+                Column::Ssem,
+                Column::Decimal,
+                Column::Instruction
+            ]))
+        );
+        assert_eq!(Columns::from_str("all").unwrap().0.len(), 7);
+        assert!(Columns::from_str("ssem,octal").is_err());
+    }
+
+    #[test]
+    fn listing() {
+        let path = Path::new("samples/ssem/fibonacci.asm");
+        let (simulator, source_map) = Simulator::from_file_mapped(path);
+        let formatter = WordFormatter::new(Columns::from_str("all").unwrap())
+            .with_annotations(annotations(path, &source_map).unwrap());
+        let listing = formatter.simulator(&simulator).to_string();
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            " CI 00000000000000000000000000000000  00000000000000000000000000000000            0  0x00000000  ///////"
+        );
+        assert_eq!(
+            lines[4],
+            " 01 11111000000000100000000000000000  00000000000000000100000000011111        16415  0x0000401F  £/T////  LDN 31   Load negative of counter"
+        );
+        assert_eq!(instruction(0b111 << 13), "STP");
+    }
+}
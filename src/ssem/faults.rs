@@ -0,0 +1,442 @@
+//! Fault injection, to study how programs cope with an unreliable Williams tube
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::random::Random;
+use super::simulator::{Simulator, StopReason};
+use super::store::Store;
+
+/// A bit to flip at a given cycle, parsed from `ADDR@CYCLE` or `ADDR:BIT@CYCLE`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScheduledFault {
+    /// The fault is injected before the instruction of this cycle executes
+    pub cycle: u64,
+    pub address: i32,
+
+    /// Bit number, 0 being the least significant one. A random bit is flipped when not given.
+    pub bit: Option<u32>,
+}
+
+impl FromStr for ScheduledFault {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<ScheduledFault, Self::Err> {
+        let invalid = || {
+            format!("invalid fault '{input}', expected ADDR@CYCLE or ADDR:BIT@CYCLE with ADDR from 0 to 31")
+        };
+        let (location, cycle) = input.split_once('@').ok_or_else(invalid)?;
+        let (address, bit) = match location.split_once(':') {
+            Some((address, bit)) => (address, Some(bit)),
+            None => (location, None),
+        };
+
+        let address = address
+            .trim()
+            .parse::<i32>()
+            .ok()
+            .filter(|address| (0..32).contains(address))
+            .ok_or_else(invalid)?;
+        let bit = match bit {
+            Some(bit) => Some(
+                bit.trim()
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|bit| *bit < 32)
+                    .ok_or_else(invalid)?,
+            ),
+            None => None,
+        };
+        Ok(ScheduledFault {
+            cycle: cycle.trim().parse().map_err(|_| invalid())?,
+            address,
+            bit,
+        })
+    }
+}
+
+/// Which faults get injected. Every draw comes from a generator seeded with `seed`, so that runs can be
+/// reproduced.
+#[derive(Debug, Clone, Default)]
+pub struct FaultOptions {
+    pub seed: u64,
+
+    /// Probability, at each cycle, that a random bit of the store flips
+    pub rate: f64,
+
+    /// Bits flipped at given cycles
+    pub schedule: Vec<ScheduledFault>,
+
+    /// Probability, at each cycle, that a charged spot of a random line leaks and its 1 reads as a 0
+    pub charge_loss: f64,
+
+    /// Number of accesses to the neighbouring lines a line withstands before one of its bits gets disturbed.
+    /// Accessing the line itself regenerates it.
+    ///
+    /// This is a simplified model of the read-around ratio of Williams tubes: reading a spot repeatedly sprays
+    /// charge onto the spots next to it.
+    pub read_around_ratio: Option<u32>,
+}
+
+impl FaultOptions {
+    pub fn is_empty(&self) -> bool {
+        self.rate <= 0.0
+            && self.schedule.is_empty()
+            && self.charge_loss <= 0.0
+            && self.read_around_ratio.is_none()
+    }
+}
+
+/// What caused a fault
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FaultKind {
+    Scheduled,
+    Random,
+    ChargeLoss,
+    ReadAround,
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultKind::Scheduled => write!(f, "scheduled"),
+            FaultKind::Random => write!(f, "random"),
+            FaultKind::ChargeLoss => write!(f, "charge loss"),
+            FaultKind::ReadAround => write!(f, "read-around"),
+        }
+    }
+}
+
+/// A bit of the store that was flipped
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fault {
+    pub cycle: u64,
+    pub address: i32,
+    pub bit: u32,
+    pub kind: FaultKind,
+
+    /// Word before and after the fault
+    pub before: i32,
+    pub after: i32,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cycle {}: bit {} of line {} flipped ({}), {} -> {}",
+            self.cycle, self.bit, self.address, self.kind, self.before, self.after
+        )
+    }
+}
+
+/// Corrupts the store of a `Simulator` before each cycle, see `Simulator::enable_fault_injection`
+#[derive(Debug)]
+pub struct FaultInjector {
+    options: FaultOptions,
+    random: Random,
+
+    /// Index in the sorted schedule of the next fault to inject
+    next_scheduled: usize,
+
+    /// Accesses to the neighbours of each line since it was last accessed
+    disturbance: Vec<u32>,
+
+    faults: Vec<Fault>,
+}
+
+impl FaultInjector {
+    pub fn new(mut options: FaultOptions) -> FaultInjector {
+        options.schedule.sort_by_key(|fault| fault.cycle);
+        FaultInjector {
+            random: Random::new(options.seed),
+            options,
+            next_scheduled: 0,
+            disturbance: Vec::new(),
+            faults: Vec::new(),
+        }
+    }
+
+    /// Faults injected so far
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    pub fn into_faults(self) -> Vec<Fault> {
+        self.faults
+    }
+
+    /// Injects the faults due before the given cycle, whose instruction is at `address`
+    pub fn inject(&mut self, cycle: u64, address: i32, store: &mut Store) {
+        while let Some(scheduled) = self.options.schedule.get(self.next_scheduled) {
+            if scheduled.cycle > cycle {
+                break;
+            }
+            self.next_scheduled += 1;
+            let scheduled = *scheduled;
+            if scheduled.cycle == cycle && (0..store.size).contains(&scheduled.address) {
+                let bit = scheduled
+                    .bit
+                    .unwrap_or_else(|| self.random.below(32) as u32);
+                self.flip(cycle, scheduled.address, bit, FaultKind::Scheduled, store);
+            }
+        }
+
+        if self.options.rate > 0.0 && self.random.chance(self.options.rate) {
+            let address = self.random.below(store.size as u64) as i32;
+            let bit = self.random.below(32) as u32;
+            self.flip(cycle, address, bit, FaultKind::Random, store);
+        }
+
+        if self.options.charge_loss > 0.0 && self.random.chance(self.options.charge_loss) {
+            let address = self.random.below(store.size as u64) as i32;
+            let word = store[address];
+            if word != 0 {
+                // Only charged spots can leak, pick one of them
+                let ones = word.count_ones();
+                let nth = self.random.below(u64::from(ones)) as u32;
+                let bit = (0..32)
+                    .filter(|bit| word & (1 << bit) != 0)
+                    .nth(nth as usize);
+                if let Some(bit) = bit {
+                    self.flip(cycle, address, bit, FaultKind::ChargeLoss, store);
+                }
+            }
+        }
+
+        if let Some(ratio) = self.options.read_around_ratio {
+            self.read_around(cycle, address, ratio, store);
+        }
+    }
+
+    /// Accounts for the lines the instruction at `address` accesses, disturbing their neighbours
+    fn read_around(&mut self, cycle: u64, address: i32, ratio: u32, store: &mut Store) {
+        if self.disturbance.len() != store.size as usize {
+            self.disturbance = vec![0; store.size as usize];
+        }
+
+        let operand = match store.decode_instruction(address) {
+            Ok((opcode, operand)) if opcode.accesses_operand() => Some(operand),
+            _ => None,
+        };
+
+        for line in std::iter::once(address).chain(operand) {
+            self.disturbance[line as usize] = 0;
+            for neighbour in [line - 1, line + 1] {
+                let neighbour = neighbour.rem_euclid(store.size);
+                self.disturbance[neighbour as usize] += 1;
+                if self.disturbance[neighbour as usize] >= ratio.max(1) {
+                    self.disturbance[neighbour as usize] = 0;
+                    let bit = self.random.below(32) as u32;
+                    self.flip(cycle, neighbour, bit, FaultKind::ReadAround, store);
+                }
+            }
+        }
+    }
+
+    fn flip(&mut self, cycle: u64, address: i32, bit: u32, kind: FaultKind, store: &mut Store) {
+        let before = store[address];
+        let after = before ^ (1 << bit);
+        store.words[address as usize] = after;
+        self.faults.push(Fault {
+            cycle,
+            address,
+            bit,
+            kind,
+            before,
+            after,
+        });
+    }
+}
+
+/// Outcome of a run with faults, compared to the same run without them
+#[derive(Debug, Clone)]
+pub struct FaultReport {
+    pub faults: Vec<Fault>,
+    pub cycles: u64,
+    pub stop_reason: Option<StopReason>,
+
+    /// Cycles run and how the run ended without faults
+    pub reference_cycles: u64,
+    pub reference_stop_reason: Option<StopReason>,
+
+    /// Addresses whose final word differs from the run without faults. None when either run was interrupted, their
+    /// stores can't be compared then.
+    pub differing_words: Option<Vec<i32>>,
+}
+
+impl FaultReport {
+    /// Whether the program reached the same final store despite the faults
+    pub fn same_final_store(&self) -> bool {
+        self.differing_words.as_ref().is_some_and(Vec::is_empty)
+    }
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = |reason: Option<StopReason>| match reason {
+            Some(reason) => reason.to_string(),
+            None => "not run".to_string(),
+        };
+
+        writeln!(f, "Injected faults: {}", self.faults.len())?;
+        for fault in &self.faults {
+            writeln!(f, "    {fault}")?;
+        }
+        writeln!(
+            f,
+            "With faults the program ran {} cycles ({}), without them {} cycles ({})",
+            self.cycles,
+            reason(self.stop_reason),
+            self.reference_cycles,
+            reason(self.reference_stop_reason),
+        )?;
+        match &self.differing_words {
+            None => write!(
+                f,
+                "The runs were interrupted, their final stores are not compared"
+            ),
+            Some(words) if words.is_empty() => {
+                write!(f, "The final store is the same as without faults")
+            }
+            Some(words) => write!(
+                f,
+                "The final store differs from the run without faults on lines {words:?}"
+            ),
+        }
+    }
+}
+
+/// Same as `Simulator::run`, injecting faults during the run. The same program is run without faults beforehand
+/// to tell whether they changed its outcome. Both runs share the interrupt of the machine: the run with faults
+/// doesn't start when the first one is interrupted.
+pub fn run_with_faults(
+    simulator: &mut Simulator,
+    max_cycles: u32,
+    options: FaultOptions,
+) -> FaultReport {
+    let mut reference = simulator.fork();
+    let reference_cycles = reference.run(max_cycles);
+    let interrupted = Some(StopReason::Interrupted);
+    if reference.stop_reason() == interrupted {
+        return FaultReport {
+            faults: Vec::new(),
+            cycles: 0,
+            stop_reason: None,
+            reference_cycles: u64::from(reference_cycles),
+            reference_stop_reason: reference.stop_reason(),
+            differing_words: None,
+        };
+    }
+
+    simulator.enable_fault_injection(options);
+    let cycles = simulator.run(max_cycles);
+    let faults = simulator.disable_fault_injection();
+
+    let differing_words = (simulator.stop_reason() != interrupted).then(|| {
+        (0..simulator.store.size)
+            .filter(|address| simulator.store[*address] != reference.store[*address])
+            .collect()
+    });
+
+    FaultReport {
+        faults,
+        cycles: u64::from(cycles),
+        stop_reason: simulator.stop_reason(),
+        reference_cycles: u64::from(reference_cycles),
+        reference_stop_reason: reference.stop_reason(),
+        differing_words,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use crate::ssem::interrupt::Interrupt;
+    use crate::ssem::simulator::{Simulator, StopReason};
+
+    use super::{run_with_faults, FaultKind, FaultOptions, ScheduledFault};
+
+    #[test]
+    fn parse_schedule() {
+        assert_eq!(
+            ScheduledFault::from_str("27:3@100"),
+            Ok(ScheduledFault {
+                cycle: 100,
+                address: 27,
+                bit: Some(3)
+            })
+        );
+        assert_eq!(ScheduledFault::from_str("5@0").unwrap().bit, None);
+        assert!(ScheduledFault::from_str("5:32@0").is_err());
+        assert!(ScheduledFault::from_str("32@0").is_err());
+        assert!(ScheduledFault::from_str("-1:0@0").is_err());
+        assert!(ScheduledFault::from_str("5").is_err());
+    }
+
+    #[test]
+    fn scheduled() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        // Changes the iteration count from 46 to 47
+        let options = FaultOptions {
+            schedule: vec![ScheduledFault::from_str("29:0@5").unwrap()],
+            ..FaultOptions::default()
+        };
+        let report = run_with_faults(&mut simulator, 10_000, options);
+
+        assert_eq!(report.faults.len(), 1);
+        assert_eq!((report.faults[0].before, report.faults[0].after), (46, 47));
+        assert_eq!(report.cycles, report.reference_cycles + 17);
+        assert!(!report.same_final_store());
+        assert!(report.differing_words.unwrap().contains(&31));
+    }
+
+    #[test]
+    fn interrupted() {
+        // Stopping the reference run skips the run with faults, and nothing is compared
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let interrupt = Interrupt::new();
+        simulator.set_interrupt(Some(interrupt.clone()));
+        interrupt.request_stop();
+        let options = FaultOptions {
+            rate: 0.5,
+            ..FaultOptions::default()
+        };
+        let report = run_with_faults(&mut simulator, 10_000, options);
+
+        assert_eq!(report.reference_stop_reason, Some(StopReason::Interrupted));
+        assert_eq!((report.cycles, report.stop_reason), (0, None));
+        assert_eq!(simulator.cycles, 0);
+        assert!(report.faults.is_empty());
+        assert_eq!(report.differing_words, None);
+        assert!(!report.same_final_store());
+        assert!(report.to_string().contains("not compared"));
+    }
+
+    #[test]
+    fn reproducible() {
+        let run = || {
+            let mut simulator = Simulator::from_file(Path::new("samples/ssem/nightmare.snp"));
+            let options = FaultOptions {
+                seed: 7,
+                rate: 0.01,
+                charge_loss: 0.01,
+                read_around_ratio: Some(50),
+                ..FaultOptions::default()
+            };
+            run_with_faults(&mut simulator, 5_000, options)
+        };
+
+        let (first, second) = (run(), run());
+        assert!(!first.faults.is_empty());
+        assert_eq!(first.faults, second.faults);
+        assert_eq!(first.differing_words, second.differing_words);
+        assert!(first
+            .faults
+            .iter()
+            .filter(|fault| fault.kind == FaultKind::ChargeLoss)
+            .all(|fault| fault.after.count_ones() < fault.before.count_ones()));
+    }
+}
@@ -0,0 +1,388 @@
+//! Export of the store tube as images while a program runs: a numbered PBM or PNG sequence, or an animated GIF
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::simulator::{ExecutedInstruction, Observer};
+use super::store::Store;
+use super::timing::{HumanDuration, TimingModel};
+
+/// Colors of the PNG and GIF images: unlit background and lit spots
+const PALETTE: [u8; 6] = [0x00, 0x00, 0x00, 0x66, 0xFF, 0x66];
+
+/// Time between two frames, parsed from a number of cycles (`1000`) or a simulated duration (`40ms`)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interval {
+    Cycles(u64),
+
+    /// Simulated time on the machine, see `TimingModel`
+    Time(Duration),
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Interval, Self::Err> {
+        let interval = match input.trim().parse::<u64>() {
+            Ok(cycles) => Interval::Cycles(cycles),
+            Err(_) => Interval::Time(
+                HumanDuration::from_str(input)
+                    .map_err(|_| {
+                        format!("invalid interval '{input}', expected a number of cycles or a duration such as 40ms")
+                    })?
+                    .0,
+            ),
+        };
+        match interval {
+            Interval::Cycles(0) => Err("the interval must be at least 1 cycle".to_string()),
+            Interval::Time(duration) if duration.is_zero() => {
+                Err("the interval must be longer than 0".to_string())
+            }
+            interval => Ok(interval),
+        }
+    }
+}
+
+/// How the spots are drawn
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum SpotStyle {
+    /// A square for each 1, nothing for 0
+    Blocks,
+
+    /// A dash for each 1 and a dot for each 0, as the tube showed them. Needs pixels of at least 3, smaller ones
+    /// are drawn as blocks.
+    #[default]
+    Dots,
+}
+
+impl FromStr for SpotStyle {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<SpotStyle, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "blocks" => Ok(SpotStyle::Blocks),
+            "dots" => Ok(SpotStyle::Dots),
+            _ => Err(format!("unknown style '{input}', expected blocks or dots")),
+        }
+    }
+}
+
+/// What the frames show and when they are taken
+#[derive(Debug, Clone)]
+pub struct FrameOptions {
+    pub interval: Interval,
+
+    /// Only these cycles are recorded
+    pub cycles: RangeInclusive<u64>,
+
+    /// Side of a spot, in pixels
+    pub pixel_size: u32,
+    pub style: SpotStyle,
+
+    /// Converts simulated time to cycles, and gives the delays of the GIF frames
+    pub timing: TimingModel,
+}
+
+/// A store picture, shown for `cycles` cycles
+struct Frame {
+    words: Vec<i32>,
+    cycles: u64,
+}
+
+/// Where frames go, decided by the extension of the path
+enum Output {
+    /// `.gif`, created with the first frame
+    Gif(Option<gif::Encoder<BufWriter<File>>>),
+
+    /// `.pbm` or `.png`, numbered from 1 before the extension
+    Sequence { png: bool },
+}
+
+/// Takes pictures of the store tube every interval, merging identical consecutive ones
+pub struct FrameExporter {
+    path: PathBuf,
+    output: Output,
+    options: FrameOptions,
+
+    /// Interval in cycles
+    every: u64,
+
+    /// Last picture, written once a different one is taken or the exporter is dropped
+    pending: Option<Frame>,
+    written: usize,
+
+    /// First error encountered, writing stops afterwards
+    error: Option<io::Error>,
+}
+
+impl FrameExporter {
+    pub fn new(path: &Path, options: FrameOptions) -> Result<FrameExporter, String> {
+        let output = match path.extension().and_then(|e| e.to_str()) {
+            Some("gif") => Output::Gif(None),
+            Some("pbm") => Output::Sequence { png: false },
+            Some("png") => Output::Sequence { png: true },
+            _ => {
+                return Err(format!(
+                    "unknown image format of '{}', expected .gif, .pbm or .png",
+                    path.display()
+                ))
+            }
+        };
+        let every = match options.interval {
+            Interval::Cycles(cycles) => cycles,
+            Interval::Time(duration) => {
+                let instruction = options.timing.instruction_duration().as_secs_f64();
+                (duration.as_secs_f64() / instruction).round().max(1.0) as u64
+            }
+        };
+        Ok(FrameExporter {
+            path: path.to_path_buf(),
+            output,
+            options,
+            every,
+            pending: None,
+            written: 0,
+            error: None,
+        })
+    }
+
+    fn capture(&mut self, store: &Store) -> io::Result<()> {
+        match &mut self.pending {
+            Some(frame) if frame.words == store.words => frame.cycles += self.every,
+            _ => {
+                let frame = Frame {
+                    words: store.words.clone(),
+                    cycles: self.every,
+                };
+                if let Some(previous) = self.pending.replace(frame) {
+                    self.write(&previous)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, frame: &Frame) -> io::Result<()> {
+        let (width, height, pixels) = render(&frame.words, &self.options);
+        self.written += 1;
+
+        match &mut self.output {
+            Output::Gif(encoder) => {
+                if encoder.is_none() {
+                    let file = BufWriter::new(File::create(&self.path)?);
+                    let mut created = gif::Encoder::new(file, width, height, &PALETTE)
+                        .map_err(io::Error::other)?;
+                    created
+                        .set_repeat(gif::Repeat::Infinite)
+                        .map_err(io::Error::other)?;
+                    *encoder = Some(created);
+                }
+                let Some(encoder) = encoder else {
+                    return Ok(());
+                };
+                // In hundredths of a second, browsers slow down shorter delays
+                let duration = self.options.timing.duration(frame.cycles);
+                let delay = (duration.as_secs_f64() * 100.0).round().clamp(2.0, 65535.0) as u16;
+                let gif_frame = gif::Frame {
+                    width,
+                    height,
+                    delay,
+                    buffer: Cow::Borrowed(&pixels),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&gif_frame).map_err(io::Error::other)
+            }
+            Output::Sequence { png } => {
+                let path = numbered(&self.path, self.written);
+                let mut file = BufWriter::new(File::create(path)?);
+                if *png {
+                    let mut encoder = png::Encoder::new(&mut file, width.into(), height.into());
+                    encoder.set_color(png::ColorType::Indexed);
+                    encoder.set_depth(png::BitDepth::Eight);
+                    encoder.set_palette(&PALETTE[..]);
+                    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+                    writer.write_image_data(&pixels).map_err(io::Error::other)?;
+                } else {
+                    write_pbm(&mut file, width, height, &pixels)?;
+                }
+                file.flush()
+            }
+        }
+    }
+
+    fn report(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            eprintln!("Unable to write the frames: {e}");
+            self.error = Some(e);
+        }
+    }
+}
+
+impl Observer for FrameExporter {
+    fn instruction_executed(&mut self, instruction: &ExecutedInstruction, store: &Store) {
+        let window = &self.options.cycles;
+        if self.error.is_none()
+            && window.contains(&instruction.cycle)
+            && (instruction.cycle - window.start()).is_multiple_of(self.every)
+        {
+            let result = self.capture(store);
+            self.report(result);
+        }
+    }
+}
+
+impl Drop for FrameExporter {
+    fn drop(&mut self) {
+        if self.error.is_none() {
+            if let Some(frame) = self.pending.take() {
+                let result = self.write(&frame);
+                self.report(result);
+            }
+        }
+    }
+}
+
+/// Path of the given frame of a sequence, e.g. `frame-00001.png` for `frame.png`
+fn numbered(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    path.with_file_name(format!("{stem}-{number:05}.{extension}"))
+}
+
+/// Pixels of the store tube, one byte per pixel: 1 where lit. Bits are drawn least significant first.
+fn render(words: &[i32], options: &FrameOptions) -> (u16, u16, Vec<u8>) {
+    let size = options.pixel_size.max(1) as usize;
+    let width = 32 * size;
+    let height = words.len() * size;
+    let mut pixels = vec![0u8; width * height];
+
+    // Dots and dashes are a third of a spot high, with a gap between dashes
+    let dots = options.style == SpotStyle::Dots && size >= 3;
+    let thickness = (size / 3).max(1);
+    let top = (size - thickness) / 2;
+    let gap = (size / 6).max(1);
+
+    for (line, word) in words.iter().enumerate() {
+        for (bit, lit) in Store::bits(*word).enumerate() {
+            let (x0, y0) = (bit * size, line * size);
+            let (xs, ys) = if !dots {
+                if !lit {
+                    continue;
+                }
+                (0..size, 0..size)
+            } else if lit {
+                (0..size - gap, top..top + thickness)
+            } else {
+                (0..thickness, top..top + thickness)
+            };
+            for y in ys {
+                for x in xs.clone() {
+                    pixels[(y0 + y) * width + x0 + x] = 1;
+                }
+            }
+        }
+    }
+    (width as u16, height as u16, pixels)
+}
+
+/// Writes a binary PBM image, lit pixels in white on black
+fn write_pbm(output: &mut impl Write, width: u16, height: u16, pixels: &[u8]) -> io::Result<()> {
+    writeln!(output, "P4\n{width} {height}")?;
+    for row in pixels.chunks(width as usize) {
+        // 1 is black in PBM, rows are padded to whole bytes
+        let bytes: Vec<u8> = row
+            .chunks(8)
+            .map(|chunk| {
+                chunk.iter().enumerate().fold(0u8, |byte, (i, lit)| {
+                    byte | (u8::from(*lit == 0) << (7 - i))
+                })
+            })
+            .collect();
+        output.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use crate::ssem::simulator::Simulator;
+    use crate::ssem::testing::TempDir;
+    use crate::ssem::timing::TimingModel;
+
+    use super::{numbered, FrameExporter, FrameOptions, Interval, SpotStyle};
+
+    fn options(interval: &str, pixel_size: u32, style: SpotStyle) -> FrameOptions {
+        FrameOptions {
+            interval: Interval::from_str(interval).unwrap(),
+            cycles: 0..=9_999,
+            pixel_size,
+            style,
+            timing: TimingModel::REPLICA,
+        }
+    }
+
+    #[test]
+    fn parse_interval() {
+        assert_eq!(Interval::from_str("250"), Ok(Interval::Cycles(250)));
+        assert_eq!(
+            Interval::from_str("40ms"),
+            Ok(Interval::Time(Duration::from_millis(40)))
+        );
+        assert!(Interval::from_str("0").is_err());
+        assert!(Interval::from_str("often").is_err());
+    }
+
+    #[test]
+    fn gif() {
+        let dir = TempDir::new();
+        let path = dir.path("nightmare.gif");
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/nightmare.snp"));
+        let exporter = FrameExporter::new(&path, options("100ms", 4, SpotStyle::Dots)).unwrap();
+        simulator.add_observer(Box::new(exporter));
+        simulator.run(10_000);
+        drop(simulator.take_observers());
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (128, 128));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert!(frame.delay >= 10);
+            frames += 1;
+        }
+        // 10 000 cycles at 700 ips last about 14 s, a frame every 100 ms at most
+        assert!((2..=143).contains(&frames));
+    }
+
+    #[test]
+    fn merged_sequence() {
+        // The store never changes, all the frames are the same
+        let dir = TempDir::new();
+        let mut simulator = Simulator::from_file(&dir.write("idle.asm", "00 NUM 0\n01 JMP 0\n"));
+
+        let path = dir.path("idle.pbm");
+        let exporter = FrameExporter::new(&path, options("10", 1, SpotStyle::Blocks)).unwrap();
+        simulator.add_observer(Box::new(exporter));
+        simulator.run(1_000);
+        drop(simulator.take_observers());
+
+        let first = numbered(&path, 1);
+        let image = fs::read(first).unwrap();
+        assert!(!numbered(&path, 2).exists());
+        assert!(image.starts_with(b"P4\n32 32\n"));
+        assert_eq!(image.len(), 9 + 32 * 4);
+        // Both words are 0, nothing is lit
+        assert!(image[9..].iter().all(|byte| *byte == 0xFF));
+    }
+}
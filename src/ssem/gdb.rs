@@ -0,0 +1,691 @@
+//! GDB remote serial protocol stub
+//!
+//! Lets `gdb` (or any front-end speaking the protocol) attach to the simulator:
+//! ```
+//! (gdb) target remote localhost:1234
+//! ```
+//! The store is exposed as 32 little-endian 32-bit words, so the word at address `n` is found at byte address
+//! `4 * n`. The program counter is a byte address too: it holds `4 * n` when the instruction at address `n` is the
+//! next to run, whatever the profile keeps in CI.
+//!
+//! gdb knows no architecture matching the machine and rejects target descriptions naming none, so the machine
+//! poses as a 32-bit x86, which every x86 build of gdb and gdb-multiarch accept: A is `eax` (register 0) and the
+//! program counter `eip` (register 8). The other registers of the description always read as 0. gdb then
+//! disassembles the store as x86 code, `x/32xw 0` shows it as words.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use super::simulator::{Simulator, StopReason};
+
+/// Amount of cycles run between two checks for an interruption from the debugger
+const CONTINUE_BATCH_CYCLES: u32 = 10_000;
+
+const WORD_BYTES: u32 = 4;
+
+/// Registers of the `org.gnu.gdb.i386.core` feature, in the order of the `g` packet: name, size in bits and type
+const REGISTERS: [(&str, usize, &str); 32] = [
+    ("eax", 32, "int32"),
+    ("ecx", 32, "int32"),
+    ("edx", 32, "int32"),
+    ("ebx", 32, "int32"),
+    ("esp", 32, "data_ptr"),
+    ("ebp", 32, "data_ptr"),
+    ("esi", 32, "int32"),
+    ("edi", 32, "int32"),
+    ("eip", 32, "code_ptr"),
+    ("eflags", 32, "int32"),
+    ("cs", 32, "int32"),
+    ("ss", 32, "int32"),
+    ("ds", 32, "int32"),
+    ("es", 32, "int32"),
+    ("fs", 32, "int32"),
+    ("gs", 32, "int32"),
+    ("st0", 80, "i387_ext"),
+    ("st1", 80, "i387_ext"),
+    ("st2", 80, "i387_ext"),
+    ("st3", 80, "i387_ext"),
+    ("st4", 80, "i387_ext"),
+    ("st5", 80, "i387_ext"),
+    ("st6", 80, "i387_ext"),
+    ("st7", 80, "i387_ext"),
+    ("fctrl", 32, "int"),
+    ("fstat", 32, "int"),
+    ("ftag", 32, "int"),
+    ("fiseg", 32, "int"),
+    ("fioff", 32, "int"),
+    ("foseg", 32, "int"),
+    ("fooff", 32, "int"),
+    ("fop", 32, "int"),
+];
+
+/// Register holding A
+const A_REGISTER: usize = 0;
+
+/// Register holding the program counter
+const PC_REGISTER: usize = 8;
+
+/// Target description sent to the debugger, see the module documentation
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n  \
+         <architecture>i386</architecture>\n  <feature name=\"org.gnu.gdb.i386.core\">\n",
+    );
+    for (regnum, (name, bits, kind)) in REGISTERS.iter().enumerate() {
+        xml += &format!(
+            "    <reg name=\"{name}\" bitsize=\"{bits}\" type=\"{kind}\" regnum=\"{regnum}\"/>\n"
+        );
+    }
+    xml + "  </feature>\n</target>\n"
+}
+
+/// A bidirectional byte stream to the debugger
+pub trait Connection: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+impl Connection for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+/// Waits for a debugger on the given address and serves it until it detaches.
+///
+/// # Arguments
+///
+/// * `address` - Either a TCP port (bound on localhost), a `host:port` pair, or `unix:<path>` for a Unix socket
+pub fn serve(simulator: &mut Simulator, address: &str) -> io::Result<()> {
+    if let Some(path) = address.strip_prefix("unix:") {
+        let listener = UnixListener::bind(path)?;
+        eprintln!("Waiting for GDB on unix socket {path}");
+        let (stream, _) = listener.accept()?;
+        let result = GdbStub::new(simulator, stream).serve();
+        std::fs::remove_file(path).ok();
+        result
+    } else {
+        let address = match address.parse::<u16>() {
+            Ok(port) => format!("127.0.0.1:{port}"),
+            Err(_) => address.to_string(),
+        };
+        let listener = TcpListener::bind(&address)?;
+        eprintln!("Waiting for GDB on {address}");
+        let (stream, peer) = listener.accept()?;
+        eprintln!("GDB connected from {peer}");
+        stream.set_nodelay(true)?;
+        GdbStub::new(simulator, stream).serve()
+    }
+}
+
+/// Serves the remote protocol for a single debugger connection
+pub struct GdbStub<'a, C: Connection> {
+    simulator: &'a mut Simulator,
+    connection: C,
+
+    /// Set once the debugger asked to stop acknowledging packets
+    no_ack: bool,
+
+    /// Byte read while checking for an interruption, the start of the next packet
+    pending: Option<u8>,
+}
+
+impl<'a, C: Connection> GdbStub<'a, C> {
+    pub fn new(simulator: &'a mut Simulator, connection: C) -> GdbStub<'a, C> {
+        GdbStub {
+            simulator,
+            connection,
+            no_ack: false,
+            pending: None,
+        }
+    }
+
+    /// Handles packets until the debugger detaches, kills the target or closes the connection
+    pub fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match packet.as_str() {
+                "D" | "D;1" => {
+                    self.write_packet("OK")?;
+                    break;
+                }
+                "k" => break,
+                "QStartNoAckMode" => {
+                    // The reply is still acknowledged by the debugger
+                    self.write_packet("OK")?;
+                    self.no_ack = true;
+                }
+                _ => {
+                    let reply = self.handle(&packet)?;
+                    self.write_packet(&reply)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the reply to the given packet. An empty reply means the packet is not supported.
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        let Some(command) = packet.get(..1) else {
+            return Ok(String::new());
+        };
+        let args = &packet[1..];
+        let reply = match command {
+            "?" => stop_reply(self.simulator.stop_reason()),
+            "g" => (0..REGISTERS.len())
+                .map(|register| self.read_register(register))
+                .collect(),
+            "G" => match (
+                decode_word(args.get(0..8)),
+                decode_word(args.get(8 * PC_REGISTER..8 * PC_REGISTER + 8)),
+            ) {
+                (Some(a), Some(pc)) if self.set_pc(pc) => {
+                    self.simulator.a = a;
+                    "OK".into()
+                }
+                _ => "E01".into(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < REGISTERS.len() => self.read_register(register),
+                _ => "E01".into(),
+            },
+            "P" => match args.split_once('=') {
+                Some((register, value)) => {
+                    match (
+                        usize::from_str_radix(register, 16),
+                        decode_word(Some(value)),
+                    ) {
+                        (Ok(A_REGISTER), Some(value)) => self.simulator.a = value,
+                        (Ok(PC_REGISTER), Some(pc)) if self.set_pc(pc) => (),
+                        _ => return Ok("E01".into()),
+                    }
+                    "OK".into()
+                }
+                None => "E01".into(),
+            },
+            "m" => self.read_memory(args).unwrap_or_else(|| "E01".into()),
+            "M" => self.write_memory(args).unwrap_or_else(|| "E01".into()),
+            // Both resume from the given address, if any
+            "s" | "c" if !args.is_empty() && !self.set_pc_hex(args) => "E01".into(),
+            "s" => stop_reply(self.simulator.step()),
+            "c" => self.resume()?,
+            "b" => match args {
+                "s" if self.simulator.history_start().is_some() => {
+                    match self.simulator.step_back(1) {
+                        0 => "T05replaylog:begin;".into(),
+                        _ => "T05".into(),
+                    }
+                }
+                "c" if self.simulator.history_start().is_some() => {
+                    match self.simulator.reverse_continue() {
+                        Some(reason) => stop_reply(Some(reason)),
+                        // Reached the beginning of the recorded history
+                        None => "T05replaylog:begin;".into(),
+                    }
+                }
+                _ => "E01".into(),
+            },
+            "Z" | "z" => self
+                .toggle_point(command == "Z", args)
+                .unwrap_or_else(|| "E01".into()),
+            "H" => "OK".into(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    /// Value of the given register in target byte order
+    fn read_register(&self, register: usize) -> String {
+        match register {
+            A_REGISTER => encode_word(self.simulator.a),
+            PC_REGISTER => encode_word(self.pc()),
+            _ => "00".repeat(REGISTERS[register].1 / 8),
+        }
+    }
+
+    /// Byte address of the next instruction
+    fn pc(&self) -> i32 {
+        self.simulator.next_address() * WORD_BYTES as i32
+    }
+
+    /// Makes the instruction at the given byte address the next one. Returns false if it is not a word of the store.
+    fn set_pc(&mut self, pc: i32) -> bool {
+        let address = pc / WORD_BYTES as i32;
+        if pc < 0 || pc % WORD_BYTES as i32 != 0 || address >= self.simulator.store.size {
+            return false;
+        }
+        self.simulator.set_next_address(address);
+        true
+    }
+
+    /// Same as `set_pc`, with the address in hexadecimal as in `c` and `s` packets
+    fn set_pc_hex(&mut self, pc: &str) -> bool {
+        match u32::from_str_radix(pc, 16)
+            .ok()
+            .and_then(|pc| i32::try_from(pc).ok())
+        {
+            Some(pc) => self.set_pc(pc),
+            None => false,
+        }
+    }
+
+    fn query(&self, args: &str) -> String {
+        let mut supported =
+            String::from("PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+");
+        if self.simulator.history_start().is_some() {
+            supported.push_str(";ReverseStep+;ReverseContinue+");
+        }
+
+        if args.starts_with("Supported") {
+            supported
+        } else if let Some(annex) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            // The document is sent in chunks: 'm' means more data follows, 'l' that this is the last one
+            let Some((offset, length)) = annex.split_once(',') else {
+                return "E01".into();
+            };
+            let (Ok(offset), Ok(length)) = (
+                usize::from_str_radix(offset, 16),
+                usize::from_str_radix(length, 16),
+            ) else {
+                return "E01".into();
+            };
+            let xml = target_xml();
+            let start = offset.min(xml.len());
+            let end = offset.saturating_add(length).min(xml.len());
+            let prefix = if end == xml.len() { 'l' } else { 'm' };
+            format!("{prefix}{}", &xml[start..end])
+        } else if args == "Attached" {
+            "1".into()
+        } else if args == "C" {
+            "QC1".into()
+        } else if args == "fThreadInfo" {
+            "m1".into()
+        } else if args == "sThreadInfo" {
+            "l".into()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Continues until the machine stops or the debugger interrupts it
+    fn resume(&mut self) -> io::Result<String> {
+        loop {
+            self.simulator.run(CONTINUE_BATCH_CYCLES);
+            match self.simulator.stop_reason() {
+                Some(StopReason::MaxCycles) | None => (),
+                reason => return Ok(stop_reply(reason)),
+            }
+            if self.interrupted()? {
+                return Ok("T02".into());
+            }
+        }
+    }
+
+    /// Checks whether the debugger sent an interruption (Ctrl-C) without waiting for it
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if self.pending.is_some() {
+            return Ok(false);
+        }
+        self.connection.set_nonblocking(true)?;
+        let mut byte = [0u8];
+        let result = self.connection.read(&mut byte);
+        self.connection.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) if byte[0] == 0x03 => Ok(true),
+            Ok(_) => {
+                // Kept for `read_packet`
+                self.pending = Some(byte[0]);
+                Ok(false)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// `m<address>,<length>`: reads bytes from the store
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (address, length) = parse_address_length(args)?;
+        let mut reply = String::new();
+        for byte_address in address..address.checked_add(length)? {
+            let word = self
+                .simulator
+                .store
+                .words
+                .get((byte_address / WORD_BYTES) as usize)?;
+            let byte = (word >> (8 * (byte_address % WORD_BYTES))) & 0xff;
+            reply.push_str(&format!("{byte:02x}"));
+        }
+        Some(reply)
+    }
+
+    /// `M<address>,<length>:<bytes>`: writes bytes to the store
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (location, data) = args.split_once(':')?;
+        let (address, length) = parse_address_length(location)?;
+        if data.len() != 2 * length as usize {
+            return None;
+        }
+        for (i, byte_address) in (address..address.checked_add(length)?).enumerate() {
+            let byte = i32::from_str_radix(data.get(2 * i..2 * i + 2)?, 16).ok()?;
+            let shift = 8 * (byte_address % WORD_BYTES);
+            let line = i32::try_from(byte_address / WORD_BYTES).ok()?;
+            let word = *self.simulator.store.words.get(line as usize)?;
+            self.simulator
+                .write_word(line, (word & !(0xff << shift)) | (byte << shift));
+        }
+        Some("OK".into())
+    }
+
+    /// `Z<type>,<address>,<kind>` and `z<type>,<address>,<kind>`: inserts or removes a breakpoint or watchpoint
+    fn toggle_point(&mut self, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let address =
+            i32::try_from(u32::from_str_radix(fields.next()?, 16).ok()? / WORD_BYTES).ok()?;
+        let points = match kind {
+            // Software and hardware breakpoints are the same thing here
+            "0" | "1" => &mut self.simulator.breakpoints,
+            "2" => &mut self.simulator.watchpoints,
+            // Read watchpoints are not supported
+            _ => return Some(String::new()),
+        };
+        if insert {
+            points.insert(address);
+        } else {
+            points.remove(&address);
+        }
+        Some("OK".into())
+    }
+
+    /// Reads the next packet, acknowledging it. Returns `None` when the connection is closed.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip everything until the start of a packet, including acknowledgements and stray interruptions
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => (),
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut received = [0u8; 2];
+            self.connection.read_exact(&mut received)?;
+            let received = std::str::from_utf8(&received)
+                .ok()
+                .and_then(|r| u8::from_str_radix(r, 16).ok());
+
+            if received == Some(checksum(&data)) {
+                if !self.no_ack {
+                    self.connection.write_all(b"+")?;
+                }
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            } else if !self.no_ack {
+                self.connection.write_all(b"-")?;
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.take() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0u8];
+        match self.connection.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(e) if e.kind() == ErrorKind::ConnectionReset => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.connection.write_all(packet.as_bytes())?;
+        self.connection.flush()
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Registers are sent in target byte order, which is little-endian
+fn encode_word(word: i32) -> String {
+    word.to_le_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn decode_word(hex: Option<&str>) -> Option<i32> {
+    let hex = hex?;
+    if hex.len() != 8 {
+        return None;
+    }
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(i32::from_le_bytes(bytes))
+}
+
+fn parse_address_length(args: &str) -> Option<(u32, u32)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        u32::from_str_radix(address, 16).ok()?,
+        u32::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn stop_reply(reason: Option<StopReason>) -> String {
+    match reason {
+        // The program exited with status 0
+        Some(StopReason::Halted) => "W00".into(),
+        Some(StopReason::Watchpoint(address)) => {
+            format!("T05watch:{:x};", address as u32 * WORD_BYTES)
+        }
+        Some(StopReason::Breakpoint(_)) => "T05swbreak:;".into(),
+        // SIGILL
+        Some(StopReason::InvalidInstruction(_)) => "T04".into(),
+        // SIGFPE
+        Some(StopReason::Overflow(_)) => "T08".into(),
+        // SIGINT
+        Some(StopReason::Interrupted) => "T02".into(),
+        _ => "T05".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+    use std::path::Path;
+
+    use crate::ssem::simulator::{Simulator, StopReason};
+
+    use super::{checksum, encode_word, Connection, GdbStub};
+
+    /// Replays scripted debugger input and collects the replies
+    struct Script {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for Script {
+        fn set_nonblocking(&self, _: bool) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum(data.as_bytes()))
+    }
+
+    /// Sends the given packets and returns the replies, acknowledgements excluded
+    fn exchange(simulator: &mut Simulator, packets: &[&str]) -> Vec<String> {
+        let input: String = packets.iter().map(|p| packet(p)).collect();
+        let mut stub = GdbStub::new(
+            simulator,
+            Script {
+                input: Cursor::new(input.into_bytes()),
+                output: Vec::new(),
+            },
+        );
+        stub.serve().unwrap();
+        let output = String::from_utf8(stub.connection.output).unwrap();
+        output
+            .split('$')
+            .skip(1)
+            .map(|p| p.split('#').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let replies = exchange(
+            &mut simulator,
+            &["p8", "P0=2a000000", "p0", "m74,8", "M7c,4:05000000", "m0,4"],
+        );
+        assert_eq!(
+            replies,
+            [
+                "04000000",
+                "OK",
+                "2a000000",
+                "2e00000000000000",
+                "OK",
+                "01000000"
+            ]
+        );
+        assert_eq!(simulator.a, 42);
+        assert_eq!(simulator.store.words[31], 5);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let replies = exchange(
+            &mut simulator,
+            &["Z0,24,4", "c", "p8", "s", "p8", "z0,24,4", "c"],
+        );
+        // The program counter is the byte address of the breakpoint the machine stopped on
+        assert_eq!(
+            replies,
+            [
+                "OK",
+                "T05swbreak:;",
+                "24000000",
+                "T05",
+                "28000000",
+                "OK",
+                "W00"
+            ]
+        );
+        // Fibonacci number 46
+        assert_eq!(simulator.store.words[27], 1836311903);
+    }
+
+    #[test]
+    fn resume_at_address() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        // Line 8 holds STP
+        let replies = exchange(&mut simulator, &["P8=20000000", "p8", "s1d", "c80", "c20"]);
+        assert_eq!(replies, ["OK", "20000000", "E01", "E01", "W00"]);
+        assert_eq!(simulator.cycles, 1);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+    }
+
+    #[test]
+    fn gdb_handshake() {
+        // Packets sent by gdb 13 on `target remote`, up to reading the registers and the first instruction
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let replies = exchange(
+            &mut simulator,
+            &[
+                "qSupported:multiprocess+;swbreak+;hwbreak+;qRelocInsn+;fork-events+;vfork-events+;exec-events+;vContSupported+;QThreadEvents+;no-resumed+",
+                "vMustReplyEmpty",
+                "QStartNoAckMode",
+                "Hgp0.0",
+                "qXfer:features:read:target.xml:0,ffb",
+                "qTStatus",
+                "?",
+                "qfThreadInfo",
+                "qsThreadInfo",
+                "qAttached",
+                "Hc-1",
+                "qC",
+                "qOffsets",
+                "g",
+                "m4,4",
+            ],
+        );
+        assert_eq!(replies.len(), 15);
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert_eq!(&replies[1..4], ["", "OK", "OK"]);
+
+        // An architecture gdb knows, with the registers it requires of it
+        let xml = &replies[4];
+        assert!(xml.starts_with('l'));
+        assert!(xml.contains("<architecture>i386</architecture>"));
+        assert!(xml.contains(r#"<feature name="org.gnu.gdb.i386.core">"#));
+        for name in ["eax", "eip", "eflags", "st7", "fop"] {
+            assert!(xml.contains(&format!(r#"name="{name}""#)));
+        }
+
+        assert_eq!(
+            &replies[5..13],
+            ["", "T05", "m1", "l", "1", "OK", "QC1", ""]
+        );
+
+        // The registers take the size gdb computes from the description, A and the program counter in place
+        let bits: usize = xml
+            .split(r#"bitsize=""#)
+            .skip(1)
+            .map(|rest| rest.split('"').next().unwrap().parse::<usize>().unwrap())
+            .sum();
+        let registers = &replies[13];
+        assert_eq!(registers.len(), bits / 4);
+        assert_eq!(&registers[..8], "00000000");
+        assert_eq!(&registers[64..72], "04000000");
+        assert_eq!(replies[14], encode_word(simulator.store.words[1]));
+    }
+
+    #[test]
+    fn packet_after_long_run() {
+        // Runs over several batches, each checking for an interruption: the packet following is still read
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/factorct.asm"));
+        let replies = exchange(&mut simulator, &["c", "p0"]);
+        assert_eq!(replies, ["W00".to_string(), encode_word(simulator.a)]);
+        assert!(simulator.cycles > 10_000);
+    }
+}
@@ -0,0 +1,268 @@
+//! Counts of the executions, reads and writes of each store line, and their rendering as heatmaps on the layout of
+//! the store tube
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use super::opcode::Opcode;
+
+/// How many times each line of the store was accessed, see `Simulator::enable_access_counting`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessCounts {
+    /// Instructions executed from each line
+    pub executions: Vec<u64>,
+
+    /// Operands read from each line by JMP, JRP, LDN and SUB. Instruction fetches are counted as executions.
+    pub reads: Vec<u64>,
+
+    /// Words written to each line by STO
+    pub writes: Vec<u64>,
+}
+
+impl AccessCounts {
+    pub fn new(size: usize) -> AccessCounts {
+        AccessCounts {
+            executions: vec![0; size],
+            reads: vec![0; size],
+            writes: vec![0; size],
+        }
+    }
+
+    /// Counts an instruction executed from `address` with the given operand
+    #[inline(always)]
+    pub fn record(&mut self, address: i32, opcode: Opcode, operand: i32) {
+        self.executions[address as usize] += 1;
+        match opcode {
+            Opcode::STO => self.writes[operand as usize] += 1,
+            Opcode::JMP | Opcode::JRP | Opcode::LDN | Opcode::SUB | Opcode::SUB2 => {
+                self.reads[operand as usize] += 1
+            }
+            _ => (),
+        }
+    }
+}
+
+/// How counts are mapped to colors
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Scale {
+    Linear,
+
+    /// Logarithm of the counts, so that lines used a few times still show next to inner loops
+    #[default]
+    Log,
+}
+
+impl Scale {
+    /// Heat of a count from 0 (never) to 1 (the most)
+    pub fn intensity(&self, count: u64, max: u64) -> f64 {
+        if max == 0 {
+            return 0.0;
+        }
+        match self {
+            Scale::Linear => count as f64 / max as f64,
+            Scale::Log => (count as f64).ln_1p() / (max as f64).ln_1p(),
+        }
+    }
+}
+
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Scale, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Scale::Linear),
+            "log" => Ok(Scale::Log),
+            _ => Err(format!("unknown scale '{input}', expected linear or log")),
+        }
+    }
+}
+
+/// Colors from cold to hot: black, red, orange, yellow and white, as a glowing phosphor
+fn color(intensity: f64) -> (u8, u8, u8) {
+    const STOPS: [(f64, f64, f64); 5] = [
+        (0.0, 0.0, 0.0),
+        (160.0, 20.0, 20.0),
+        (240.0, 120.0, 0.0),
+        (255.0, 220.0, 40.0),
+        (255.0, 255.0, 230.0),
+    ];
+    let position = intensity.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let t = position - index as f64;
+    let (from, to) = (STOPS[index], STOPS[index + 1]);
+    let mix = |a: f64, b: f64| (a + (b - a) * t).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+/// Width of the tube of each count in the terminal, in characters, for the three to fit in 80 columns. The SVG
+/// image draws them 32 spots wide like the store tube.
+const TERMINAL_WIDTH: usize = 14;
+
+/// The executions, reads and writes of each line side by side, one tube each.
+///
+/// The `Display` implementation draws them in the terminal with shades, in ANSI colors with `color`, `write_svg` as
+/// an image.
+pub struct Heatmap<'a> {
+    pub counts: &'a AccessCounts,
+    pub scale: Scale,
+    pub color: bool,
+}
+
+impl Heatmap<'_> {
+    pub fn new(counts: &AccessCounts, scale: Scale) -> Heatmap<'_> {
+        Heatmap {
+            counts,
+            scale,
+            color: false,
+        }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Title and counts of each tube
+    fn tubes(&self) -> [(&'static str, &[u64]); 3] {
+        [
+            ("Executed", &self.counts.executions),
+            ("Read", &self.counts.reads),
+            ("Written", &self.counts.writes),
+        ]
+    }
+
+    /// Writes an SVG image of the tubes, each line hinting its count
+    pub fn write_svg(&self, output: &mut impl Write) -> io::Result<()> {
+        const SPOT: usize = 12;
+        const MARGIN: usize = 40;
+        let lines = self.counts.executions.len();
+        let tube_width = 32 * SPOT;
+        let width = MARGIN + 3 * (tube_width + MARGIN);
+        let height = 2 * MARGIN + lines * SPOT;
+
+        writeln!(
+            output,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" font-family="monospace" font-size="10">"#
+        )?;
+        writeln!(
+            output,
+            r##"<rect width="100%" height="100%" fill="#111"/>"##
+        )?;
+        for (tube, (title, counts)) in self.tubes().into_iter().enumerate() {
+            let x = MARGIN + tube * (tube_width + MARGIN);
+            let max = counts.iter().copied().max().unwrap_or(0);
+            writeln!(
+                output,
+                r##"<text x="{x}" y="{}" fill="#ccc">{title} (up to {max})</text>"##,
+                MARGIN - 8
+            )?;
+            for (line, count) in counts.iter().enumerate() {
+                let y = MARGIN + line * SPOT;
+                let (r, g, b) = color(self.scale.intensity(*count, max));
+                writeln!(
+                    output,
+                    r##"<rect x="{x}" y="{y}" width="{tube_width}" height="{}" fill="#{r:02x}{g:02x}{b:02x}"><title>Line {line}: {count}</title></rect>"##,
+                    SPOT - 2
+                )?;
+                if tube == 0 {
+                    writeln!(
+                        output,
+                        r##"<text x="{}" y="{}" fill="#ccc" text-anchor="end">{line:02}</text>"##,
+                        x - 6,
+                        y + SPOT - 3
+                    )?;
+                }
+            }
+        }
+        writeln!(output, "</svg>")?;
+        output.flush()
+    }
+}
+
+impl fmt::Display for Heatmap<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tubes = self.tubes();
+        let maxima = tubes.map(|(_, counts)| counts.iter().copied().max().unwrap_or(0));
+
+        let mut header = "    ".to_string();
+        for (title, _) in tubes {
+            header += &format!(" {title:<width$}", width = TERMINAL_WIDTH + 10);
+        }
+        writeln!(f, "{}", header.trim_end())?;
+        for line in 0..self.counts.executions.len() {
+            write!(f, " {line:02} ")?;
+            for ((_, counts), max) in tubes.iter().zip(maxima) {
+                let count = counts[line];
+                let intensity = self.scale.intensity(count, max);
+                // Shades keep the hottest lines visible without colors
+                let shade = match count {
+                    0 => ' ',
+                    _ if intensity < 0.25 => '░',
+                    _ if intensity < 0.5 => '▒',
+                    _ if intensity < 0.75 => '▓',
+                    _ => '█',
+                };
+                let shades = shade.to_string().repeat(TERMINAL_WIDTH);
+                if self.color {
+                    let (r, g, b) = color(intensity);
+                    write!(
+                        f,
+                        " \x1b[38;2;{r};{g};{b};48;2;16;16;16m{shades}\x1b[0m {count:>9}"
+                    )?;
+                } else {
+                    write!(f, " {shades} {count:>9}")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::ssem::simulator::Simulator;
+
+    use super::{Heatmap, Scale};
+
+    #[test]
+    fn counts() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        simulator.enable_access_counting();
+        let cycles = simulator.run(10_000);
+        let counts = simulator.access_counts().unwrap();
+
+        assert_eq!(counts.executions.iter().sum::<u64>(), u64::from(cycles));
+        // Line 31 is the counter, written once per loop
+        assert!(counts.writes[31] > 0);
+        assert_eq!(counts.writes[0], 0);
+        assert!(counts.reads[31] >= counts.writes[31]);
+    }
+
+    #[test]
+    fn rendering() {
+        assert_eq!(Scale::Log.intensity(0, 100), 0.0);
+        assert_eq!(Scale::Log.intensity(100, 100), 1.0);
+        assert!(Scale::Log.intensity(10, 100_000) > Scale::Linear.intensity(10, 100_000) * 100.0);
+
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/factorct.asm"));
+        simulator.enable_access_counting();
+        simulator.run(100_000);
+        let heatmap = Heatmap::new(simulator.access_counts().unwrap(), Scale::Log);
+
+        let text = heatmap.to_string();
+        assert_eq!(text.lines().count(), 1 + 32);
+        assert!(text.contains('█') && !text.contains('\x1b'));
+        let heatmap = heatmap.with_color(true);
+        assert!(heatmap.to_string().contains("\x1b[38;2;"));
+
+        let mut svg = Vec::new();
+        heatmap.write_svg(&mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<title>").count(), 3 * 32);
+    }
+}
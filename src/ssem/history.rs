@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+
+use super::store::Store;
+
+/// State of the machine before a cycle was executed
+#[derive(Debug, Copy, Clone)]
+pub struct UndoEntry {
+    pub a: i32,
+    pub ci: i32,
+
+    /// Address written by the cycle, by STO or by a peripheral it was read from, and the word it held before
+    pub write: Option<(i32, i32)>,
+}
+
+/// Full copy of the machine taken before executing the given cycle
+#[derive(Debug)]
+struct Checkpoint {
+    cycle: u64,
+    a: i32,
+    ci: i32,
+    words: Vec<i32>,
+}
+
+/// Undo log used to run the machine backwards
+///
+/// Every executed cycle pushes the values of A and CI it is about to replace, along with the previous content of the
+/// word it writes to. Full copies of the machine are taken periodically so that large jumps back only need to undo
+/// a few entries.
+///
+/// Words changed from outside the program between two cycles, by faults, debuggers or the front panel, are logged
+/// apart and undone along with the cycles preceding them.
+///
+/// The log holds at most `capacity` entries: the oldest ones are dropped as the machine runs, moving the horizon
+/// beyond which the machine can not go back.
+#[derive(Debug)]
+pub struct History {
+    entries: VecDeque<UndoEntry>,
+
+    /// External writes: end cycle of the log when they happened, address and the word it held before
+    edits: VecDeque<(u64, i32, i32)>,
+
+    /// Cycle number of `entries[0]`
+    first_cycle: u64,
+
+    checkpoints: VecDeque<Checkpoint>,
+    checkpoint_interval: u64,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates an empty log
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of cycles that can be undone
+    /// * `checkpoint_interval` - Number of cycles between two full copies of the machine
+    pub fn new(capacity: usize, checkpoint_interval: u64) -> History {
+        if capacity == 0 || checkpoint_interval == 0 {
+            panic!("History capacity and checkpoint interval must be greater than zero");
+        }
+        History {
+            entries: VecDeque::with_capacity(capacity.min(1 << 16)),
+            edits: VecDeque::new(),
+            first_cycle: 0,
+            checkpoints: VecDeque::new(),
+            checkpoint_interval,
+            capacity,
+        }
+    }
+
+    /// Earliest cycle the machine can go back to
+    pub fn first_cycle(&self) -> u64 {
+        self.first_cycle
+    }
+
+    /// Cycle following the last recorded one
+    pub fn end_cycle(&self) -> u64 {
+        self.first_cycle + self.entries.len() as u64
+    }
+
+    /// Records the state of the machine before executing the given cycle
+    pub fn record(&mut self, cycle: u64, entry: UndoEntry, store: &Store) {
+        if self.entries.is_empty() || cycle != self.end_cycle() {
+            // The machine moved without us (history just enabled or state loaded): start over
+            self.clear();
+            self.first_cycle = cycle;
+        }
+
+        if cycle.is_multiple_of(self.checkpoint_interval) {
+            self.checkpoints.push_back(Checkpoint {
+                cycle,
+                a: entry.a,
+                ci: entry.ci,
+                words: store.words.clone(),
+            });
+        }
+        self.entries.push_back(entry);
+
+        if self.entries.len() > self.capacity {
+            self.entries.pop_front();
+            self.first_cycle += 1;
+            // Edits at the horizon can't be undone anymore, the cycle before them being forgotten
+            while self
+                .edits
+                .front()
+                .is_some_and(|(cycle, _, _)| *cycle <= self.first_cycle)
+            {
+                self.edits.pop_front();
+            }
+            while self
+                .checkpoints
+                .front()
+                .is_some_and(|c| c.cycle < self.first_cycle)
+            {
+                self.checkpoints.pop_front();
+            }
+        }
+    }
+
+    /// Records that the word at `address`, which held `previous`, was written from outside the program before the
+    /// next cycle
+    pub fn record_edit(&mut self, address: i32, previous: i32) {
+        // Without recorded cycles, there is nothing to go back to past the edit
+        if !self.entries.is_empty() {
+            self.edits.push_back((self.end_cycle(), address, previous));
+        }
+    }
+
+    /// Undoes the last recorded cycle, and the edits following it, and returns its entry
+    pub fn undo(&mut self, a: &mut i32, ci: &mut i32, store: &mut Store) -> Option<UndoEntry> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let end = self.end_cycle();
+        while let Some((_, address, word)) = self.edits.back().filter(|(cycle, _, _)| *cycle >= end)
+        {
+            store.words[*address as usize] = *word;
+            self.edits.pop_back();
+        }
+        let entry = self.entries.pop_back()?;
+        *a = entry.a;
+        *ci = entry.ci;
+        if let Some((address, word)) = entry.write {
+            store.words[address as usize] = word;
+        }
+        self.drop_future_checkpoints();
+        Some(entry)
+    }
+
+    /// Brings the machine back to the state it had before executing the given cycle
+    ///
+    /// Restores the closest checkpoint that is not earlier than `target` then undoes the remaining entries.
+    /// Returns the cycle actually reached, which is clamped to the history horizon.
+    pub fn rewind(&mut self, target: u64, a: &mut i32, ci: &mut i32, store: &mut Store) -> u64 {
+        let target = target.max(self.first_cycle);
+        if target >= self.end_cycle() {
+            return self.end_cycle();
+        }
+
+        if let Some(checkpoint) = self.checkpoints.iter().find(|c| c.cycle >= target) {
+            *a = checkpoint.a;
+            *ci = checkpoint.ci;
+            store.words.copy_from_slice(&checkpoint.words);
+            self.entries
+                .truncate((checkpoint.cycle - self.first_cycle) as usize);
+            // The checkpoint holds the edits made up to its cycle
+            while self
+                .edits
+                .back()
+                .is_some_and(|(cycle, _, _)| *cycle > checkpoint.cycle)
+            {
+                self.edits.pop_back();
+            }
+            self.drop_future_checkpoints();
+        }
+        while self.end_cycle() > target {
+            self.undo(a, ci, store);
+        }
+
+        target
+    }
+
+    /// Returns the last cycle that wrote to the given address, if it is still in the log
+    pub fn last_write(&self, address: i32) -> Option<u64> {
+        self.entries
+            .iter()
+            .rposition(|e| matches!(e.write, Some((addr, _)) if addr == address))
+            .map(|i| self.first_cycle + i as u64)
+    }
+
+    /// Checkpoints at or after the end of the log would be duplicated when the cycles are executed again
+    fn drop_future_checkpoints(&mut self) {
+        let end = self.end_cycle();
+        while self.checkpoints.back().is_some_and(|c| c.cycle >= end) {
+            self.checkpoints.pop_back();
+        }
+    }
+
+    /// Forgets every recorded cycle
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.edits.clear();
+        self.checkpoints.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ssem::store::Store;
+
+    use super::{History, UndoEntry};
+
+    fn entry(a: i32, ci: i32, write: Option<(i32, i32)>) -> UndoEntry {
+        UndoEntry { a, ci, write }
+    }
+
+    #[test]
+    fn undo() {
+        let mut store = Store::new();
+        let mut history = History::new(8, 4);
+
+        history.record(0, entry(0, 0, None), &store);
+        history.record(1, entry(5, 1, Some((3, 0))), &store);
+        store.words[3] = 5;
+
+        let (mut a, mut ci) = (5, 2);
+        let undone = history.undo(&mut a, &mut ci, &mut store).unwrap();
+        assert_eq!(undone.write, Some((3, 0)));
+        assert_eq!((a, ci, store.words[3]), (5, 1, 0));
+        assert_eq!(history.end_cycle(), 1);
+    }
+
+    #[test]
+    fn edits() {
+        let mut store = Store::new();
+        let mut history = History::new(8, 4);
+
+        history.record(0, entry(0, 0, None), &store);
+        history.record_edit(5, 0);
+        store.words[5] = 9;
+        history.record(1, entry(0, 1, None), &store);
+
+        let (mut a, mut ci) = (0, 2);
+        history.undo(&mut a, &mut ci, &mut store);
+        assert_eq!(store.words[5], 9);
+        history.undo(&mut a, &mut ci, &mut store);
+        assert_eq!(store.words[5], 0);
+    }
+
+    #[test]
+    fn bounded() {
+        let store = Store::new();
+        let mut history = History::new(10, 4);
+        for cycle in 0..100 {
+            history.record(cycle, entry(0, 0, None), &store);
+        }
+        assert_eq!(history.entries.len(), 10);
+        assert_eq!(history.first_cycle(), 90);
+        assert!(history.checkpoints.len() <= 3);
+    }
+
+    #[test]
+    fn last_write() {
+        let store = Store::new();
+        let mut history = History::new(10, 4);
+        history.record(0, entry(0, 0, Some((7, 0))), &store);
+        history.record(1, entry(0, 1, Some((8, 0))), &store);
+        history.record(2, entry(0, 2, None), &store);
+        assert_eq!(history.last_write(7), Some(0));
+        assert_eq!(history.last_write(8), Some(1));
+        assert_eq!(history.last_write(9), None);
+    }
+}
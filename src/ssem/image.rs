@@ -0,0 +1,383 @@
+//! Import of monochrome or grayscale images into store words, to draw pictures on the store tube
+//!
+//! Images are read from the Netpbm formats, PBM for black and white and PGM for grayscale, in their plain or raw
+//! variants. Each row of pixels becomes a word, the leftmost pixel being the least significant bit as the tube
+//! shows it. Bright pixels are lit spots, the way `--frames` pictures the tube.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How the gray levels are reduced to lit and unlit spots
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Dithering {
+    /// Each pixel is compared to the threshold
+    #[default]
+    None,
+
+    /// The error of each pixel is spread to its neighbours, giving smooth gradients
+    FloydSteinberg,
+
+    /// Pixels are compared to a threshold varying in a 4×4 Bayer pattern, giving regular textures
+    Ordered,
+}
+
+impl FromStr for Dithering {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Dithering, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "none" => Ok(Dithering::None),
+            "floyd-steinberg" | "fs" => Ok(Dithering::FloydSteinberg),
+            "ordered" | "bayer" => Ok(Dithering::Ordered),
+            _ => Err(format!(
+                "unknown dithering '{input}', expected none, floyd-steinberg or ordered"
+            )),
+        }
+    }
+}
+
+/// Source format of the block of words
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum BlockFormat {
+    /// `NN NUM value` lines
+    #[default]
+    Asm,
+
+    /// `NN: bits` lines
+    Snp,
+}
+
+impl FromStr for BlockFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<BlockFormat, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "asm" => Ok(BlockFormat::Asm),
+            "snp" => Ok(BlockFormat::Snp),
+            _ => Err(format!("unknown format '{input}', expected asm or snp")),
+        }
+    }
+}
+
+/// How the pixels become bits
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImportOptions {
+    /// Brightness from 0 (black) to 1 (white) above which a pixel is lit
+    pub threshold: f64,
+    pub dithering: Dithering,
+
+    /// Light the dark pixels instead, for pictures drawn in black on white
+    pub invert: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            threshold: 0.5,
+            dithering: Dithering::None,
+            invert: false,
+        }
+    }
+}
+
+/// A grayscale picture
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+
+    /// Brightness of each pixel from 0 (black) to 1 (white), row by row
+    pub pixels: Vec<f64>,
+}
+
+impl Image {
+    /// Reads a PBM (P1, P4) or PGM (P2, P5) file
+    pub fn from_file(path: &Path) -> io::Result<Image> {
+        Image::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> io::Result<Image> {
+        let mut header = Header { data, position: 0 };
+        let magic = header.token()?;
+        let (bitmap, plain) = match magic.as_str() {
+            "P1" => (true, true),
+            "P2" => (false, true),
+            "P4" => (true, false),
+            "P5" => (false, false),
+            _ => {
+                return Err(invalid(format!(
+                    "unsupported image format '{magic}', expected a PBM or PGM image"
+                )))
+            }
+        };
+        let width = header.number()?;
+        let height = header.number()?;
+        let max = if bitmap { 1 } else { header.number()? };
+        if max == 0 || max > 65535 {
+            return Err(invalid(format!("invalid maximum gray value {max}")));
+        }
+
+        // A pixel takes at least a bit, which bounds the sizes worth allocating for
+        let count = width
+            .checked_mul(height)
+            .filter(|count| *count <= data.len() * 8)
+            .ok_or_else(|| invalid("the image is truncated".to_string()))?;
+        let pixels: Vec<f64> = if plain {
+            // Plain PBM digits may be written without separators
+            let values: Vec<usize> = if bitmap {
+                header.data[header.position..]
+                    .iter()
+                    .filter(|byte| !byte.is_ascii_whitespace())
+                    .take(count)
+                    .map(|byte| match byte {
+                        b'0' | b'1' => Ok(usize::from(byte - b'0')),
+                        _ => Err(invalid("invalid PBM pixel".to_string())),
+                    })
+                    .collect::<io::Result<_>>()?
+            } else {
+                (0..count)
+                    .map(|_| header.number())
+                    .collect::<io::Result<_>>()?
+            };
+            if values.len() < count {
+                return Err(invalid("the image is truncated".to_string()));
+            }
+            values
+                .into_iter()
+                .map(|value| value as f64 / max as f64)
+                .collect()
+        } else {
+            // A single whitespace separates the header from the raster
+            let raster = &header.data[(header.position + 1).min(header.data.len())..];
+            if bitmap {
+                let row_bytes = width.div_ceil(8);
+                if raster.len() < row_bytes * height {
+                    return Err(invalid("the image is truncated".to_string()));
+                }
+                (0..count)
+                    .map(|i| {
+                        let (y, x) = (i / width, i % width);
+                        u8::from(raster[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0)
+                    })
+                    .map(f64::from)
+                    .collect()
+            } else {
+                let size = if max > 255 { 2 } else { 1 };
+                if raster.len() < count * size {
+                    return Err(invalid("the image is truncated".to_string()));
+                }
+                raster
+                    .chunks(size)
+                    .take(count)
+                    .map(|bytes| {
+                        bytes
+                            .iter()
+                            .fold(0usize, |value, byte| value << 8 | usize::from(*byte))
+                    })
+                    .map(|value| value as f64 / max as f64)
+                    .collect()
+            }
+        };
+
+        // In PBM images 1 is black
+        let pixels = if bitmap {
+            pixels.into_iter().map(|pixel| 1.0 - pixel).collect()
+        } else {
+            pixels
+        };
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// One word per row of pixels, the leftmost being the least significant bit
+    pub fn to_words(&self, options: &ImportOptions) -> Result<Vec<i32>, String> {
+        if self.width > 32 {
+            return Err(format!(
+                "the image is {} pixels wide, the store words only have 32 bits",
+                self.width
+            ));
+        }
+        let mut levels: Vec<f64> = self
+            .pixels
+            .iter()
+            .map(|pixel| if options.invert { 1.0 - pixel } else { *pixel })
+            .collect();
+
+        let mut words = vec![0i32; self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let level = levels[y * self.width + x];
+                let lit = match options.dithering {
+                    Dithering::None | Dithering::FloydSteinberg => level > options.threshold,
+                    Dithering::Ordered => {
+                        const BAYER: [[u8; 4]; 4] =
+                            [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+                        let offset = (f64::from(BAYER[y % 4][x % 4]) + 0.5) / 16.0 - 0.5;
+                        level > options.threshold + offset
+                    }
+                };
+                if lit {
+                    words[y] |= 1 << x;
+                }
+
+                if options.dithering == Dithering::FloydSteinberg {
+                    let error = level - if lit { 1.0 } else { 0.0 };
+                    let mut spread = |dx: isize, dy: usize, weight: f64| {
+                        let nx = x as isize + dx;
+                        if (0..self.width as isize).contains(&nx) && y + dy < self.height {
+                            levels[(y + dy) * self.width + nx as usize] += error * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                }
+            }
+        }
+        Ok(words)
+    }
+}
+
+/// Writes words as source lines numbered from `address`, ready to be pasted in a program
+pub fn write_block(
+    output: &mut impl Write,
+    words: &[i32],
+    address: i32,
+    format: BlockFormat,
+) -> Result<(), String> {
+    let start = usize::try_from(address)
+        .ok()
+        .filter(|start| start.checked_add(words.len()).is_some_and(|end| end <= 32))
+        .ok_or_else(|| {
+            format!(
+                "the {} words do not fit in the store from line {address}",
+                words.len()
+            )
+        })?;
+    let write = |output: &mut dyn Write| -> io::Result<()> {
+        for (offset, word) in words.iter().enumerate() {
+            let line = start + offset;
+            match format {
+                BlockFormat::Asm => writeln!(output, "{line:02} NUM {word}")?,
+                BlockFormat::Snp => writeln!(output, "{line:02}: {:032b}", word.reverse_bits())?,
+            }
+        }
+        output.flush()
+    };
+    write(output).map_err(|e| e.to_string())
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the whitespace-separated fields of a Netpbm header, skipping `#` comments
+struct Header<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Header<'_> {
+    fn token(&mut self) -> io::Result<String> {
+        loop {
+            match self.data.get(self.position) {
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(b'#') => {
+                    while self
+                        .data
+                        .get(self.position)
+                        .is_some_and(|byte| *byte != b'\n')
+                    {
+                        self.position += 1;
+                    }
+                }
+                Some(_) => break,
+                None => return Err(invalid("the image is truncated".to_string())),
+            }
+        }
+        let start = self.position;
+        while self
+            .data
+            .get(self.position)
+            .is_some_and(|byte| !byte.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+        Ok(String::from_utf8_lossy(&self.data[start..self.position]).into_owned())
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        let token = self.token()?;
+        token
+            .parse()
+            .map_err(|_| invalid(format!("invalid number '{token}' in the image")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_block, BlockFormat, Dithering, Image, ImportOptions};
+
+    #[test]
+    fn bitmap() {
+        // A black arrow on white, plain and raw
+        let plain = b"P1\n# arrow\n4 2\n1000\n1 1 1 1\n";
+        let image = Image::parse(plain).unwrap();
+        let raw = Image::parse(b"P4 4 2\n\x80\xF0").unwrap();
+        assert_eq!(image, raw);
+
+        let options = ImportOptions {
+            invert: true,
+            ..ImportOptions::default()
+        };
+        // The leftmost pixel is the least significant bit
+        assert_eq!(image.to_words(&options), Ok(vec![0b0001, 0b1111]));
+        assert_eq!(
+            image.to_words(&ImportOptions::default()),
+            Ok(vec![0b1110, 0])
+        );
+
+        let mut block = Vec::new();
+        write_block(&mut block, &[1, -1], 30, BlockFormat::Snp).unwrap();
+        assert_eq!(
+            String::from_utf8(block).unwrap(),
+            format!("30: 1{}\n31: {}\n", "0".repeat(31), "1".repeat(32))
+        );
+        assert!(write_block(&mut Vec::new(), &[1, 2, 3], 30, BlockFormat::Asm).is_err());
+        assert!(write_block(&mut Vec::new(), &[1], -1, BlockFormat::Asm).is_err());
+        assert!(write_block(&mut Vec::new(), &[1], i32::MIN, BlockFormat::Asm).is_err());
+    }
+
+    #[test]
+    fn dithering() {
+        // A mid-gray square in a raw PGM
+        let mut data = b"P5\n32 16\n255\n".to_vec();
+        data.extend([128u8; 32 * 16]);
+        let image = Image::parse(&data).unwrap();
+        let lit = |dithering| {
+            let options = ImportOptions {
+                dithering,
+                ..ImportOptions::default()
+            };
+            let words = image.to_words(&options).unwrap();
+            words.iter().map(|word| word.count_ones()).sum::<u32>()
+        };
+
+        assert_eq!(lit(Dithering::None), 32 * 16);
+        assert!((240..=272).contains(&lit(Dithering::FloydSteinberg)));
+        assert_eq!(lit(Dithering::Ordered), 32 * 16 / 2);
+
+        let wide = Image {
+            width: 33,
+            height: 1,
+            pixels: vec![0.0; 33],
+        };
+        assert!(wide.to_words(&ImportOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Stopping a run or asking for its progress from outside of it: signals, timeouts or other threads
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Requests to a running `Simulator`, see `Simulator::set_interrupt`.
+///
+/// The requests are only flags, so that they can be made from signal handlers.
+#[derive(Debug)]
+pub struct Interrupt {
+    /// Set along with any of the requests below. This is the only flag `Simulator::run` checks at each cycle
+    pending: Arc<AtomicBool>,
+
+    stop: Arc<AtomicBool>,
+    progress: Arc<AtomicBool>,
+    timed_out: AtomicBool,
+
+    /// When and at which cycle progress was last reported, to compute the speed since
+    last_report: Mutex<Option<(Instant, u64)>>,
+}
+
+impl Interrupt {
+    pub fn new() -> Arc<Interrupt> {
+        Arc::new(Interrupt {
+            pending: Arc::new(AtomicBool::new(false)),
+            stop: Arc::new(AtomicBool::new(false)),
+            progress: Arc::new(AtomicBool::new(false)),
+            timed_out: AtomicBool::new(false),
+            last_report: Mutex::new(None),
+        })
+    }
+
+    /// Stop the run after the current instruction. The request stays, following runs stop right away.
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Report the progress of the run on the standard error, without stopping it
+    pub fn request_progress(&self) {
+        self.progress.store(true, Ordering::Relaxed);
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Request a stop once the given duration has elapsed
+    pub fn stop_after(self: &Arc<Self>, duration: Duration) {
+        let interrupt = Arc::clone(self);
+        thread::spawn(move || {
+            thread::sleep(duration);
+            interrupt.timed_out.store(true, Ordering::Relaxed);
+            interrupt.request_stop();
+        });
+    }
+
+    /// Requests a stop on SIGINT (Ctrl-C) and progress on SIGUSR1. A second SIGINT exits the process, in case the
+    /// run is blocked, for instance waiting for input.
+    pub fn register_signals(&self) -> io::Result<()> {
+        use signal_hook::consts::SIGINT;
+        use signal_hook::flag;
+
+        flag::register_conditional_shutdown(SIGINT, 130, Arc::clone(&self.stop))?;
+        flag::register(SIGINT, Arc::clone(&self.stop))?;
+        flag::register(SIGINT, Arc::clone(&self.pending))?;
+
+        #[cfg(unix)]
+        {
+            use signal_hook::consts::SIGUSR1;
+            flag::register(SIGUSR1, Arc::clone(&self.progress))?;
+            flag::register(SIGUSR1, Arc::clone(&self.pending))?;
+        }
+        Ok(())
+    }
+
+    /// Whether a request is waiting to be handled
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// The flag behind `is_pending`, for the run loop to check it without going through the `Interrupt`
+    pub(super) fn pending_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.pending)
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Whether the stop was requested by `stop_after`
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Handles the pending requests for a machine in the given state. Returns whether the run must stop.
+    #[cold]
+    pub(super) fn handle(&self, cycles: u64, ci: i32, a: i32) -> bool {
+        self.pending.swap(false, Ordering::Acquire);
+        if self.progress.swap(false, Ordering::Relaxed) {
+            eprintln!("{}", self.progress(cycles, ci, a));
+        }
+        if self.stop_requested() {
+            // Keeps the following runs from starting
+            self.pending.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    fn progress(&self, cycles: u64, ci: i32, a: i32) -> Progress {
+        let now = Instant::now();
+        let mut last_report = self.last_report.lock().unwrap_or_else(|e| e.into_inner());
+        let ips = last_report
+            .map(|(time, last_cycles)| (cycles - last_cycles) as f64 / (now - time).as_secs_f64());
+        *last_report = Some((now, cycles));
+        Progress { cycles, ci, a, ips }
+    }
+}
+
+/// State of a run, reported on request
+#[derive(Debug, Copy, Clone)]
+pub struct Progress {
+    pub cycles: u64,
+    pub ci: i32,
+    pub a: i32,
+
+    /// Speed since the previous report, if any
+    pub ips: Option<f64>,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Progress: cycle {}, CI = {}, A = {}",
+            self.cycles, self.ci, self.a
+        )?;
+        if let Some(ips) = self.ips {
+            write!(f, ", {ips:.0} ips since the previous report")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crate::ssem::simulator::{Simulator, StopReason};
+
+    use super::Interrupt;
+
+    #[test]
+    fn stop() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/nightmare.snp"));
+        let interrupt = Interrupt::new();
+        simulator.set_interrupt(Some(interrupt.clone()));
+
+        interrupt.request_progress();
+        assert_eq!(simulator.run(1000), 1000);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::MaxCycles));
+
+        interrupt.request_stop();
+        assert_eq!(simulator.run(1000), 0);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Interrupted));
+        assert!(!interrupt.timed_out());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/nightmare.snp"));
+        let interrupt = Interrupt::new();
+        simulator.set_interrupt(Some(interrupt.clone()));
+        interrupt.stop_after(Duration::from_millis(20));
+
+        let cycles = simulator.run(u32::MAX);
+        assert!(cycles < u32::MAX);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Interrupted));
+        assert!(interrupt.timed_out());
+    }
+}
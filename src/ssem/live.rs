@@ -0,0 +1,320 @@
+//! Changing the store while a program runs: scheduled pokes and commands read from the operator
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Instant;
+
+use super::simulator::{Simulator, StopReason};
+use super::throttle::{Pacer, PacingReport};
+
+/// Number of cycles between two checks of the commands, when the run is not paced
+const BATCH_CYCLES: u32 = 10_000;
+
+/// A write to the store at a given cycle, parsed from `ADDR=VALUE@CYCLE`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Poke {
+    pub address: i32,
+    pub value: i32,
+
+    /// The word is written before the instruction of this cycle executes
+    pub cycle: u64,
+}
+
+impl FromStr for Poke {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Poke, Self::Err> {
+        let invalid = || format!("invalid poke '{input}', expected ADDR=VALUE@CYCLE");
+        let (write, cycle) = input.split_once('@').ok_or_else(invalid)?;
+        let (address, value) = write.split_once('=').ok_or_else(invalid)?;
+        Ok(Poke {
+            address: address.trim().parse().map_err(|_| invalid())?,
+            value: value.trim().parse().map_err(|_| invalid())?,
+            cycle: cycle.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A command of the live mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Command {
+    /// `poke ADDR VALUE` or `poke ADDR=VALUE`
+    Poke(i32, i32),
+
+    /// `peek ADDR`
+    Peek(i32),
+
+    /// `pause`, suspending the run until `resume`
+    Pause,
+    Resume,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Command, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid command '{input}', expected poke ADDR VALUE, peek ADDR, pause or resume"
+            )
+        };
+        let words: Vec<&str> = input
+            .split(|c: char| c.is_whitespace() || c == '=')
+            .filter(|w| !w.is_empty())
+            .collect();
+        let number = |index: usize| -> Result<i32, String> {
+            words
+                .get(index)
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(invalid)
+        };
+
+        match words.first().map(|w| w.to_ascii_lowercase()).as_deref() {
+            Some("poke") if words.len() == 3 => Ok(Command::Poke(number(1)?, number(2)?)),
+            Some("peek") if words.len() == 2 => Ok(Command::Peek(number(1)?)),
+            Some("pause") if words.len() == 1 => Ok(Command::Pause),
+            Some("resume") if words.len() == 1 => Ok(Command::Resume),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Reads commands, one per line, on a thread of their own. `None` reads the standard input.
+///
+/// A FIFO is opened again each time its writer closes it, so that commands can be sent with successive
+/// `echo ... > fifo`.
+pub fn read_commands(path: Option<PathBuf>) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let forward = |input: &mut dyn BufRead| {
+            for line in input.lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    return false;
+                }
+            }
+            true
+        };
+        match path {
+            None => {
+                forward(&mut io::stdin().lock());
+            }
+            Some(path) => {
+                while let Ok(file) = File::open(&path) {
+                    if !forward(&mut BufReader::new(file)) || !is_fifo(&path) {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// Same as `Simulator::run`, writing the scheduled pokes to the store on time and executing the commands received
+/// between cycles. Replies to the commands are written to `output`.
+///
+/// The run is paced when `ips` is given.
+pub fn run_live(
+    simulator: &mut Simulator,
+    max_cycles: u32,
+    ips: Option<f64>,
+    mut pokes: Vec<Poke>,
+    commands: Option<Receiver<String>>,
+    output: &mut impl Write,
+) -> (u32, Option<PacingReport>) {
+    pokes.sort_by_key(|poke| poke.cycle);
+    let mut pokes = pokes.into_iter().peekable();
+    let mut pacer = ips.map(Pacer::new);
+    let batch = pacer.as_ref().map_or(BATCH_CYCLES, |p| p.slice_cycles());
+    let mut cycles = 0u32;
+
+    while cycles < max_cycles {
+        while let Some(poke) = pokes.next_if(|poke| poke.cycle <= simulator.cycles) {
+            write_word(simulator, poke.address, poke.value, output);
+        }
+
+        if let Some(commands) = &commands {
+            execute_commands(simulator, commands, pacer.as_mut(), output);
+        }
+
+        let until_poke = pokes
+            .peek()
+            .map_or(u64::MAX, |poke| poke.cycle - simulator.cycles);
+        let slice = (batch.min(max_cycles - cycles) as u64).min(until_poke) as u32;
+        cycles += simulator.run(slice);
+        if simulator.stop_reason() != Some(StopReason::MaxCycles) {
+            break;
+        }
+
+        if let Some(pacer) = &mut pacer {
+            pacer.wait(cycles);
+        }
+    }
+
+    (cycles, pacer.map(|pacer| pacer.report(cycles)))
+}
+
+/// Executes the commands received since the last call, waiting for `resume` after a `pause`
+fn execute_commands(
+    simulator: &mut Simulator,
+    commands: &Receiver<String>,
+    mut pacer: Option<&mut Pacer>,
+    output: &mut impl Write,
+) {
+    let mut paused: Option<Instant> = None;
+    loop {
+        let line = if paused.is_some() {
+            commands.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            commands.try_recv()
+        };
+        let line = match line {
+            Ok(line) => line,
+            // Either no more commands for now, or nobody left to resume the run
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse::<Command>() {
+            Ok(Command::Poke(address, value)) => write_word(simulator, address, value, output),
+            Ok(Command::Peek(address)) => {
+                if (0..simulator.store.size).contains(&address) {
+                    reply(
+                        output,
+                        format_args!("line {address} = {}", simulator.store[address]),
+                    );
+                } else {
+                    reply(output, format_args!("line {address} is outside the store"));
+                }
+            }
+            Ok(Command::Pause) => {
+                if paused.is_none() {
+                    paused = Some(Instant::now());
+                    reply(output, format_args!("paused at cycle {}", simulator.cycles));
+                }
+            }
+            Ok(Command::Resume) => {
+                if let Some(since) = paused.take() {
+                    if let Some(pacer) = pacer.as_deref_mut() {
+                        pacer.suspended(since.elapsed());
+                    }
+                    reply(output, format_args!("resumed"));
+                }
+            }
+            Err(message) => reply(output, format_args!("{message}")),
+        }
+    }
+}
+
+fn write_word(simulator: &mut Simulator, address: i32, value: i32, output: &mut impl Write) {
+    if (0..simulator.store.size).contains(&address) {
+        simulator.write_word(address, value);
+        reply(
+            output,
+            format_args!("line {address} <- {value} at cycle {}", simulator.cycles),
+        );
+    } else {
+        reply(output, format_args!("line {address} is outside the store"));
+    }
+}
+
+fn reply(output: &mut impl Write, message: std::fmt::Arguments) {
+    if writeln!(output, "{message}")
+        .and_then(|_| output.flush())
+        .is_err()
+    {
+        eprintln!("{message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::sync::mpsc;
+
+    use crate::ssem::simulator::{Simulator, StopReason};
+
+    use super::{run_live, Command, Poke};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Poke::from_str("22=512@1000"),
+            Ok(Poke {
+                address: 22,
+                value: 512,
+                cycle: 1000
+            })
+        );
+        assert!(Poke::from_str("22=512").is_err());
+        assert_eq!(Command::from_str("poke 22 -5"), Ok(Command::Poke(22, -5)));
+        assert_eq!(Command::from_str("POKE 22=5"), Ok(Command::Poke(22, 5)));
+        assert_eq!(Command::from_str("peek 3"), Ok(Command::Peek(3)));
+        assert!(Command::from_str("peek").is_err());
+    }
+
+    #[test]
+    fn scheduled() {
+        // Feeding the virtual pet keeps it from looping on its sad face
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/virpet.asm"));
+        let pokes = vec![Poke {
+            address: 22,
+            value: 500,
+            cycle: 10_000,
+        }];
+        let mut output = Vec::new();
+        let (cycles, pacing) = run_live(&mut simulator, 10_100, None, pokes, None, &mut output);
+
+        assert_eq!(cycles, 10_100);
+        assert!(pacing.is_none());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "line 22 <- 500 at cycle 10000\n"
+        );
+        // The pet eats one unit every few instructions
+        assert!((450..500).contains(&simulator.store[22]));
+    }
+
+    #[test]
+    fn commands() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let (sender, receiver) = mpsc::channel();
+        for command in ["peek 29", "poke 29 10", "nonsense"] {
+            sender.send(command.to_string()).unwrap();
+        }
+        drop(sender);
+
+        let mut output = Vec::new();
+        run_live(
+            &mut simulator,
+            100_000,
+            None,
+            vec![],
+            Some(receiver),
+            &mut output,
+        );
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("line 29 = 46\nline 29 <- 10 at cycle 0\ninvalid command"));
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!(simulator.store[31], 10);
+    }
+}
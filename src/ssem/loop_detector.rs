@@ -0,0 +1,109 @@
+use super::store::Store;
+
+/// Complete state of the machine
+#[derive(Debug, Clone)]
+pub struct MachineState {
+    pub a: i32,
+    pub ci: i32,
+    pub words: Vec<i32>,
+}
+
+impl MachineState {
+    fn matches(&self, a: i32, ci: i32, store: &Store) -> bool {
+        self.a == a && self.ci == ci && self.words == store.words
+    }
+}
+
+/// Detects when the machine goes back to a state it has already been in, meaning it loops forever.
+///
+/// This uses Brent's cycle detection algorithm: a state is saved at cycles that are powers of two apart and compared
+/// with each following state. Memory usage is constant and the period found is exact.
+#[derive(Debug)]
+pub struct LoopDetector {
+    /// State when detection started, to later find where the loop begins
+    initial: MachineState,
+    start_cycle: u64,
+
+    saved: MachineState,
+
+    /// Number of cycles after which the saved state gets replaced
+    power: u64,
+
+    /// Number of cycles since the state was saved
+    elapsed: u64,
+}
+
+impl LoopDetector {
+    pub fn new(a: i32, ci: i32, store: &Store, cycle: u64) -> LoopDetector {
+        let initial = MachineState {
+            a,
+            ci,
+            words: store.words.clone(),
+        };
+        LoopDetector {
+            saved: initial.clone(),
+            initial,
+            start_cycle: cycle,
+            power: 1,
+            elapsed: 0,
+        }
+    }
+
+    /// State the detection started from
+    pub fn initial(&self) -> &MachineState {
+        &self.initial
+    }
+
+    /// Cycle at which the detection started
+    pub fn start_cycle(&self) -> u64 {
+        self.start_cycle
+    }
+
+    /// Checks the state following a cycle. Returns the period of the loop once detected.
+    pub fn check(&mut self, a: i32, ci: i32, store: &Store) -> Option<u64> {
+        self.elapsed += 1;
+        if self.saved.matches(a, ci, store) {
+            return Some(self.elapsed);
+        }
+
+        if self.elapsed == self.power {
+            self.saved.a = a;
+            self.saved.ci = ci;
+            self.saved.words.copy_from_slice(&store.words);
+            self.power *= 2;
+            self.elapsed = 0;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ssem::store::Store;
+
+    use super::LoopDetector;
+
+    #[test]
+    fn period() {
+        // A counter going through 0, 1, 2 after a lead-in of 5 cycles
+        let store = Store::new();
+        let state = |cycle: i32| {
+            if cycle < 5 {
+                100 + cycle
+            } else {
+                (cycle - 5) % 3
+            }
+        };
+
+        let mut detector = LoopDetector::new(state(0), 0, &store, 0);
+        let found = (1..100).find_map(|cycle| detector.check(state(cycle), 0, &store));
+        assert_eq!(found, Some(3));
+    }
+
+    #[test]
+    fn no_loop() {
+        let store = Store::new();
+        let mut detector = LoopDetector::new(0, 0, &store, 0);
+        assert!((1..10_000).all(|cycle| detector.check(cycle, 0, &store).is_none()));
+    }
+}
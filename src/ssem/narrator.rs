@@ -0,0 +1,216 @@
+//! Teaching mode: explains every executed instruction in plain English
+
+use std::fmt;
+use std::io::{self, Write};
+
+use super::opcode::Opcode;
+use super::simulator::{ExecutedInstruction, Observer};
+use super::store::Store;
+
+/// Writes a sentence for each executed instruction, using the actual values involved
+pub struct Narrator<W: Write> {
+    output: W,
+
+    /// First error encountered, writing stops afterwards
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Narrator<W> {
+    pub fn new(output: W) -> Narrator<W> {
+        Narrator {
+            output,
+            error: None,
+        }
+    }
+}
+
+impl<W: Write> Observer for Narrator<W> {
+    fn instruction_executed(&mut self, instruction: &ExecutedInstruction, store: &Store) {
+        if self.error.is_none() {
+            let sentence = explain(instruction, store.size);
+            if let Err(e) = writeln!(self.output, "{sentence}") {
+                eprintln!("Unable to write the narration: {e}");
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl<W: Write> Drop for Narrator<W> {
+    fn drop(&mut self) {
+        if self.error.is_none() {
+            self.output.flush().ok();
+        }
+    }
+}
+
+/// Describes what the given instruction did
+///
+/// # Arguments
+///
+/// * `store_size` - Number of words in the store, as CI wraps around it
+pub fn explain(instruction: &ExecutedInstruction, store_size: i32) -> String {
+    let i = instruction;
+    let n = i.operand;
+    let value = i.operand_value.unwrap_or_default();
+    let next_line = i.next_address;
+
+    let explanation = match i.opcode {
+        Opcode::JMP => format!(
+            "jump to the address held in line {n}, which is {value}; \
+             CI is now {}, so the next instruction is taken from line {next_line}",
+            i.ci_after
+        ),
+        Opcode::JRP => format!(
+            "jump relative by the value held in line {n}, which is {value}; \
+             CI becomes {} + {} = {}, so the next instruction is taken from line {next_line}",
+            i.ci_after.wrapping_sub(value),
+            Signed(value),
+            i.ci_after
+        ),
+        Opcode::LDN => format!(
+            "load the negative of line {n}, which holds {value}, into the accumulator; A is now {}{}",
+            i.a_after,
+            if value == i32::MIN {
+                " (it does not fit in 32 bits and wrapped around)"
+            } else {
+                ""
+            }
+        ),
+        Opcode::STO => format!("store the accumulator ({}) into line {n}", i.a_after),
+        Opcode::SUB | Opcode::SUB2 => format!(
+            "subtract line {n}, which holds {value}, from the accumulator: {} - {} = {}; A is now {}{}",
+            i.a_before,
+            Signed(value),
+            i.a_after,
+            i.a_after,
+            if i.a_before.checked_sub(value).is_none() {
+                " (the result does not fit in 32 bits and wrapped around)"
+            } else {
+                ""
+            }
+        ),
+        Opcode::CMP => {
+            let skipped = (i.address + 1).rem_euclid(store_size);
+            if next_line != skipped {
+                format!(
+                    "test the accumulator: A is {}, so the next instruction (line {skipped}) is skipped and \
+                     execution continues at line {next_line}",
+                    i.a_before
+                )
+            } else {
+                format!(
+                    "test the accumulator: A is {}, which is not negative, so the next instruction \
+                     (line {skipped}) runs as normal",
+                    i.a_before
+                )
+            }
+        }
+        Opcode::STP => "stop the machine".to_string(),
+        Opcode::NUM => "not an instruction".to_string(),
+    };
+
+    let mnemonic = match i.opcode {
+        Opcode::CMP | Opcode::STP => i.opcode.to_string(),
+        opcode => format!("{opcode} {n}"),
+    };
+    format!("CI={}: {} — {}", i.address, mnemonic, explanation)
+}
+
+/// Displays negative numbers between parentheses, for readable arithmetic
+struct Signed(i32);
+
+impl fmt::Display for Signed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 < 0 {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ssem::opcode::Opcode;
+    use crate::ssem::simulator::ExecutedInstruction;
+
+    use super::explain;
+
+    fn instruction(opcode: Opcode, operand: i32, value: i32) -> ExecutedInstruction {
+        ExecutedInstruction {
+            cycle: 0,
+            address: 9,
+            word: 0,
+            opcode,
+            operand,
+            operand_value: Some(value),
+            a_before: 4,
+            a_after: 4,
+            ci_after: 9,
+            next_address: 10,
+        }
+    }
+
+    #[test]
+    fn ldn() {
+        let i = ExecutedInstruction {
+            a_after: -13,
+            ..instruction(Opcode::LDN, 27, 13)
+        };
+        assert_eq!(
+            explain(&i, 32),
+            "CI=9: LDN 27 — load the negative of line 27, which holds 13, into the accumulator; A is now -13"
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        let i = ExecutedInstruction {
+            address: 16,
+            ci_after: 13,
+            next_address: 14,
+            ..instruction(Opcode::JRP, 26, -3)
+        };
+        assert_eq!(
+            explain(&i, 32),
+            "CI=16: JRP 26 — jump relative by the value held in line 26, which is -3; \
+             CI becomes 16 + (-3) = 13, so the next instruction is taken from line 14"
+        );
+
+        let i = ExecutedInstruction {
+            ci_after: 31,
+            next_address: 0,
+            ..instruction(Opcode::JMP, 30, 31)
+        };
+        assert!(
+            explain(&i, 32).ends_with("CI is now 31, so the next instruction is taken from line 0")
+        );
+    }
+
+    #[test]
+    fn cmp() {
+        let i = ExecutedInstruction {
+            a_before: -1,
+            operand_value: None,
+            ci_after: 10,
+            next_address: 11,
+            ..instruction(Opcode::CMP, 0, 0)
+        };
+        assert_eq!(
+            explain(&i, 32),
+            "CI=9: CMP — test the accumulator: A is -1, \
+             so the next instruction (line 10) is skipped and execution continues at line 11"
+        );
+    }
+
+    #[test]
+    fn sub_overflow() {
+        let i = ExecutedInstruction {
+            a_before: i32::MIN,
+            a_after: i32::MAX,
+            ..instruction(Opcode::SUB, 0, 1)
+        };
+        assert!(explain(&i, 32).ends_with("wrapped around)"));
+    }
+}
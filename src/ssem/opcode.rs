@@ -5,6 +5,7 @@ use std::str::FromStr;
 /// Represents an operation code for the SSEM
 ///
 /// Its values gives the opcode bits, except for NUM
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Opcode {
     /// Indirect jump
@@ -35,6 +36,13 @@ pub enum Opcode {
     NUM,
 }
 
+impl Opcode {
+    /// Whether the instruction reads or writes the store line given as operand
+    pub fn accesses_operand(&self) -> bool {
+        !matches!(self, Opcode::CMP | Opcode::STP | Opcode::NUM)
+    }
+}
+
 lazy_static! {
     pub static ref SSEM_OPCODE_TABLE: FxHashMap<u8, Opcode> = {
         let mut m = FxHashMap::default();
@@ -0,0 +1,146 @@
+//! Detection of signed overflows in the arithmetic instructions, LDN and SUB
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::opcode::Opcode;
+
+/// What happens when LDN or SUB give a result that doesn't fit in 32 bits
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum OverflowMode {
+    /// Wrap around silently, like the hardware
+    #[default]
+    Wrap,
+
+    /// Stop the machine after the instruction, with `StopReason::Overflow`
+    Trap,
+
+    /// Keep running and record each overflow
+    Log,
+}
+
+impl FromStr for OverflowMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<OverflowMode, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "wrap" => Ok(OverflowMode::Wrap),
+            "trap" => Ok(OverflowMode::Trap),
+            "log" => Ok(OverflowMode::Log),
+            _ => Err(format!(
+                "unknown overflow mode '{input}', expected wrap, trap or log"
+            )),
+        }
+    }
+}
+
+/// An arithmetic instruction whose result wrapped around
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Overflow {
+    pub cycle: u64,
+
+    /// Address of the instruction (value of CI during its execution)
+    pub address: i32,
+
+    pub opcode: Opcode,
+
+    /// Accumulator before the instruction
+    pub a: i32,
+
+    /// Word read from the store
+    pub operand: i32,
+
+    /// Wrapped result, left in the accumulator
+    pub result: i32,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let exact = match self.opcode {
+            Opcode::LDN => format!("-({})", self.operand),
+            _ => format!("{} - ({})", self.a, self.operand),
+        };
+        write!(
+            f,
+            "cycle {}, CI={}: {} computed {} which wrapped around to {}",
+            self.cycle, self.address, self.opcode, exact, self.result
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ssem::opcode::Opcode;
+    use crate::ssem::simulator::{Simulator, StopReason};
+    use crate::ssem::testing::TempDir;
+
+    use super::OverflowMode;
+
+    /// Runs the given program until it stops, with overflows handled as given
+    fn run(source: &str, mode: OverflowMode) -> Simulator {
+        let dir = TempDir::new();
+        let mut simulator = Simulator::from_file(&dir.write("overflow.asm", source));
+        simulator.overflow_mode = mode;
+        simulator.run(100);
+        simulator
+    }
+
+    const LDN_MIN: &str = "00 NUM 0\n01 LDN 3\n02 STP\n03 NUM -2147483648\n";
+
+    /// A is -2, minus the largest positive number
+    const SUB_MAX: &str = "00 NUM 0\n01 LDN 4\n02 SUB 5\n03 STP\n04 NUM 2\n05 NUM 2147483647\n";
+
+    #[test]
+    fn ldn() {
+        let simulator = run(LDN_MIN, OverflowMode::Log);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!(simulator.overflow_count(), 1);
+        let overflow = simulator.overflows()[0];
+        assert_eq!((overflow.cycle, overflow.address), (0, 1));
+        assert_eq!(overflow.opcode, Opcode::LDN);
+        assert_eq!((overflow.operand, overflow.result), (i32::MIN, i32::MIN));
+        assert_eq!(
+            overflow.to_string(),
+            "cycle 0, CI=1: LDN computed -(-2147483648) which wrapped around to -2147483648"
+        );
+
+        let simulator = run(LDN_MIN, OverflowMode::Trap);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Overflow(1)));
+        assert_eq!((simulator.cycles, simulator.a), (1, i32::MIN));
+    }
+
+    #[test]
+    fn sub() {
+        let simulator = run(SUB_MAX, OverflowMode::Log);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!(simulator.overflow_count(), 1);
+        let overflow = simulator.overflows()[0];
+        assert_eq!((overflow.cycle, overflow.address), (1, 2));
+        assert_eq!(overflow.opcode, Opcode::SUB);
+        assert_eq!((overflow.a, overflow.operand), (-2, i32::MAX));
+        assert_eq!(overflow.result, i32::MAX);
+
+        let simulator = run(SUB_MAX, OverflowMode::Trap);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Overflow(2)));
+        assert_eq!((simulator.cycles, simulator.a), (2, i32::MAX));
+    }
+
+    #[test]
+    fn wrap() {
+        let simulator = run(LDN_MIN, OverflowMode::Wrap);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!((simulator.a, simulator.overflow_count()), (i32::MIN, 0));
+        assert!(simulator.overflows().is_empty());
+
+        let simulator = run(SUB_MAX, OverflowMode::Wrap);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!((simulator.a, simulator.overflow_count()), (i32::MAX, 0));
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!("TRAP".parse(), Ok(OverflowMode::Trap));
+        assert_eq!("log".parse(), Ok(OverflowMode::Log));
+        assert!("saturate".parse::<OverflowMode>().is_err());
+    }
+}
@@ -0,0 +1,642 @@
+//! Front panel of the 1998 replica: switches, keys and monitor, operated by hand or from a script
+//!
+//! Scripts are written the way the Computer Conservation Society describes its test procedures, e.g.
+//! "Set Line (L) switches to 31." or "RUN (If the Stop light is lit press the Kc switch first).", so that the
+//! procedures found in the comments of `samples/ssem/tests/*.snp` can be run as they are.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::simulator::{Simulator, StopReason};
+use super::store::Store;
+
+/// Where instructions are taken from, selected by the Auto/Manual switch
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    /// From the store, as a program runs
+    Auto,
+
+    /// From the line and function switches
+    Manual,
+}
+
+/// What the monitor tube displays
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Monitor {
+    Store,
+    Accumulator,
+    Control,
+}
+
+/// Keys of the panel
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Key {
+    /// Clear Control (CI and the present instruction), which also turns the Stop light off
+    KC,
+
+    /// Clear the whole Store
+    KSC,
+
+    /// Clear the store line selected by the line switches
+    KLC,
+
+    /// Clear the Accumulator
+    KAC,
+
+    /// Execute a single instruction
+    SingleShot,
+
+    /// Typewriter key, toggling the given bit (0 being the least significant) of the line selected by the line
+    /// switches. Only works with the Read/Write switch on Write.
+    Typewriter(u32),
+}
+
+/// The machine and the state of its controls
+pub struct FrontPanel {
+    pub simulator: Simulator,
+
+    /// Line (L) switches, giving the store line used by manual instructions, KLC and the typewriter
+    pub line_switches: i32,
+
+    /// Function (F) switches, giving the function of manual instructions
+    pub function_switches: i32,
+
+    pub mode: Mode,
+
+    /// Read/Write switch, on Write the typewriter can change the store
+    pub write: bool,
+
+    pub monitor: Monitor,
+
+    /// Most cycles a RUN executes before handing control back, as the machine would otherwise run forever
+    pub run_budget: u32,
+
+    /// Stop/Run switch
+    running: bool,
+
+    /// Lit once the machine executed STP, until KC is pressed. The machine doesn't run while it is lit.
+    stop_light: bool,
+}
+
+impl FrontPanel {
+    /// Panel of the given machine, with the switches in their normal run position and the Stop/Run switch on Stop
+    pub fn new(simulator: Simulator, run_budget: u32) -> FrontPanel {
+        let mut panel = FrontPanel {
+            simulator,
+            line_switches: 0,
+            function_switches: 0,
+            mode: Mode::Auto,
+            write: true,
+            monitor: Monitor::Store,
+            run_budget,
+            running: false,
+            stop_light: false,
+        };
+        panel.normal_run_position();
+        panel
+    }
+
+    /// Puts the switches in their normal run position: L switches to 31 and F switches to 7 (all switches down),
+    /// Auto and Write
+    pub fn normal_run_position(&mut self) {
+        self.line_switches = 31;
+        self.function_switches = 7;
+        self.mode = Mode::Auto;
+        self.write = true;
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn stop_light(&self) -> bool {
+        self.stop_light
+    }
+
+    /// Instruction set on the line and function switches
+    pub fn switches_word(&self) -> i32 {
+        (self.function_switches & 0b111) << 13 | (self.line_switches & 0b11111)
+    }
+
+    /// Flips the Stop/Run switch. Setting it to Run starts the machine unless the Stop light is lit, and runs it
+    /// until it stops or `run_budget` cycles are executed.
+    ///
+    /// Returns the number of cycles executed.
+    pub fn set_running(&mut self, running: bool) -> u32 {
+        let started = running && !self.running;
+        self.set_run_switch(running);
+        if !started {
+            return 0;
+        }
+        self.run_for(self.run_budget)
+    }
+
+    /// Flips the Stop/Run switch without running the machine, for front ends that run it with `run_for`
+    pub fn set_run_switch(&mut self, running: bool) {
+        self.running = running;
+    }
+
+    /// Runs at most the given amount of cycles while the Stop/Run switch is on Run and the Stop light is off, for
+    /// front ends that keep the machine running between refreshes.
+    ///
+    /// Returns the number of cycles executed.
+    pub fn run_for(&mut self, max_cycles: u32) -> u32 {
+        if !self.running || self.stop_light {
+            return 0;
+        }
+
+        match self.mode {
+            Mode::Auto => {
+                let cycles = self.simulator.run(max_cycles);
+                self.update_stop_light();
+                cycles
+            }
+            Mode::Manual => {
+                let mut cycles = 0;
+                while cycles < max_cycles && !self.stop_light {
+                    self.simulator.manual_cycle(self.switches_word());
+                    self.update_stop_light();
+                    cycles += 1;
+                }
+                cycles
+            }
+        }
+    }
+
+    pub fn press(&mut self, key: Key) {
+        let line = self.line_switches.rem_euclid(self.simulator.store.size);
+        match key {
+            Key::KC => {
+                // Clearing CI makes the machine start from line 1, whichever way the profile increments it
+                self.simulator.set_next_address(1);
+                self.stop_light = false;
+            }
+            Key::KSC => {
+                for line in 0..self.simulator.store.words.len() as i32 {
+                    self.simulator.write_word(line, 0);
+                }
+            }
+            Key::KLC => self.simulator.write_word(line, 0),
+            Key::KAC => self.simulator.a = 0,
+            Key::SingleShot => {
+                if self.stop_light {
+                    return;
+                }
+                match self.mode {
+                    Mode::Auto => {
+                        self.simulator.step();
+                    }
+                    Mode::Manual => self.simulator.manual_cycle(self.switches_word()),
+                }
+                self.update_stop_light();
+            }
+            Key::Typewriter(bit) => {
+                if self.write && bit < 32 {
+                    let word = self.simulator.store[line] ^ (1 << bit);
+                    self.simulator.write_word(line, word);
+                }
+            }
+        }
+    }
+
+    /// Replaces the contents of the store with the given file, as the replica is loaded from a PC
+    pub fn load(&mut self, path: &Path) {
+        self.simulator
+            .replace_store(Store::from_file_mapped(path).0);
+    }
+
+    fn update_stop_light(&mut self) {
+        if self.simulator.stop_reason() == Some(StopReason::Halted) {
+            self.stop_light = true;
+        }
+    }
+
+    /// Executes the operator procedure written in the given script, reporting on `output`.
+    ///
+    /// Files are loaded relatively to `directory`. Lines that are not commands are commentary and get ignored.
+    pub fn run_script(
+        &mut self,
+        script: &str,
+        directory: &Path,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        for (number, line) in script.lines().enumerate() {
+            let commands = match parse_line(line) {
+                Ok(commands) => commands,
+                Err(message) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("line {}: {message}", number + 1),
+                    ))
+                }
+            };
+            if !commands.is_empty() {
+                writeln!(output, "> {}", line.trim().trim_start_matches(';').trim())?;
+            }
+            for command in commands {
+                self.execute(command, directory, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        command: Command,
+        directory: &Path,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        match command {
+            Command::NormalRunPosition => self.normal_run_position(),
+            Command::SetLine(line) => self.line_switches = line,
+            Command::SetFunction(function) => self.function_switches = function,
+            Command::SetRunning(running) => {
+                self.report_run(running, output)?;
+            }
+            Command::SetMode(mode) => self.mode = mode,
+            Command::SetWrite(write) => self.write = write,
+            Command::Press(key) => self.press(key),
+            Command::PressIfStopped(key) => {
+                if self.stop_light {
+                    self.press(key);
+                }
+            }
+            Command::Monitor(monitor) => {
+                self.monitor = monitor;
+                write!(output, "{}", self.display())?;
+            }
+            Command::Load(path) => self.load(&directory.join(path)),
+            Command::Run => {
+                // The switch is flicked to Run then back, as the replica operators do
+                self.set_running(false);
+                self.report_run(true, output)?;
+            }
+            Command::Show => write!(output, "{}", self.display())?,
+        }
+        Ok(())
+    }
+
+    fn report_run(&mut self, running: bool, output: &mut impl Write) -> io::Result<()> {
+        let stopped = self.stop_light;
+        let cycles = self.set_running(running);
+        if !running {
+            return Ok(());
+        }
+        if stopped {
+            writeln!(output, "The Stop light is lit, the machine does not start")?;
+        } else if self.stop_light {
+            writeln!(output, "Stopped after {cycles} cycles")?;
+        } else {
+            writeln!(output, "Still running after {cycles} cycles")?;
+        }
+        write!(output, "{}", self.display())
+    }
+
+    /// What the monitor tube shows
+    pub fn display(&self) -> MonitorDisplay<'_> {
+        MonitorDisplay(self)
+    }
+}
+
+/// Contents of the monitor tube, bits in the order of the machine (least significant first)
+pub struct MonitorDisplay<'a>(&'a FrontPanel);
+
+impl fmt::Display for MonitorDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let simulator = &self.0.simulator;
+        match self.0.monitor {
+            Monitor::Store => write!(f, "{}", simulator.store),
+            Monitor::Accumulator => {
+                writeln!(
+                    f,
+                    " {:032b} A  = {:6}",
+                    simulator.a.reverse_bits(),
+                    simulator.a
+                )
+            }
+            Monitor::Control => {
+                let present = simulator.store[simulator.ci.rem_euclid(simulator.store.size)];
+                writeln!(
+                    f,
+                    " {:032b} CI = {:6}",
+                    simulator.ci.reverse_bits(),
+                    simulator.ci
+                )?;
+                writeln!(f, " {:032b} PI", present.reverse_bits())
+            }
+        }
+    }
+}
+
+/// An action of the operator
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    NormalRunPosition,
+    SetLine(i32),
+    SetFunction(i32),
+    SetRunning(bool),
+    SetMode(Mode),
+    SetWrite(bool),
+    Press(Key),
+
+    /// Press the key only if the Stop light is lit
+    PressIfStopped(Key),
+    Monitor(Monitor),
+    Load(PathBuf),
+    Run,
+    Show,
+}
+
+/// Words starting a command, lines starting with anything else are commentary
+const VERBS: [&str; 12] = [
+    "set", "clear", "press", "monitor", "load", "run", "stop", "type", "single", "show", "display",
+    "normal",
+];
+
+/// Reads the commands of a line of script. Commentary gives no command.
+fn parse_line(line: &str) -> Result<Vec<Command>, String> {
+    let line = line.trim().trim_start_matches(';').trim();
+
+    // Procedures are sometimes numbered: "1) Set ..."
+    let line = match line.split_once(')') {
+        Some((number, rest)) if number.trim().parse::<u32>().is_ok() => rest.trim(),
+        _ => line,
+    };
+
+    // Parentheses hold remarks, except the condition of pressing KC first
+    let mut text = String::new();
+    let mut remarks = Vec::new();
+    let mut depth = 0;
+    for c in line.chars() {
+        match c {
+            '(' => {
+                depth += 1;
+                remarks.push(String::new());
+            }
+            ')' if depth > 0 => depth -= 1,
+            c if depth > 0 => remarks.last_mut().unwrap().push(c),
+            c => text.push(c),
+        }
+    }
+    let original: Vec<&str> = text.split_whitespace().collect();
+    let text = text.to_lowercase().replace(" /", "/").replace("/ ", "/");
+    let text = text.trim().trim_end_matches(['.', ':']).trim();
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty())
+        .map(|w| w.trim_end_matches('.'))
+        .collect();
+
+    let Some(verb) = words.first() else {
+        return Ok(Vec::new());
+    };
+    if !VERBS.contains(verb) {
+        return Ok(Vec::new());
+    }
+
+    let unknown = || Err(format!("unknown command '{}'", line.trim()));
+    let last = words.last().copied().unwrap_or_default();
+    let mut commands = Vec::new();
+
+    let first_remark = remarks.first().map(|r| r.to_lowercase());
+    if let Some(remark) = first_remark {
+        if remark.contains("stop light is lit") && remark.contains("kc") {
+            commands.push(Command::PressIfStopped(Key::KC));
+        }
+    }
+
+    let command = match *verb {
+        "set" if text.contains("normal run") => Command::NormalRunPosition,
+        "normal" => Command::NormalRunPosition,
+        "set" if text.contains("stop/run") => match last {
+            "stop" => Command::SetRunning(false),
+            "run" => Command::SetRunning(true),
+            _ => return unknown(),
+        },
+        "set" if text.contains("auto/manual") => match last {
+            "auto" => Command::SetMode(Mode::Auto),
+            "manual" => Command::SetMode(Mode::Manual),
+            _ => return unknown(),
+        },
+        "set" if text.contains("read/write") => match last {
+            "read" => Command::SetWrite(false),
+            "write" => Command::SetWrite(true),
+            _ => return unknown(),
+        },
+        "set" if words.contains(&"line") => match last.parse::<i32>() {
+            Ok(line) if (0..32).contains(&line) => Command::SetLine(line),
+            _ => return unknown(),
+        },
+        "set" if words.contains(&"function") => {
+            match last.trim_start_matches("fn").parse::<i32>() {
+                Ok(function) if (0..8).contains(&function) => Command::SetFunction(function),
+                _ => return unknown(),
+            }
+        }
+        "clear" => {
+            for word in &words[1..] {
+                match *word {
+                    "store" => commands.push(Command::Press(Key::KSC)),
+                    "accumulator" => commands.push(Command::Press(Key::KAC)),
+                    "control" => commands.push(Command::Press(Key::KC)),
+                    "line" => commands.push(Command::Press(Key::KLC)),
+                    _ => (),
+                }
+            }
+            if commands.is_empty() {
+                return unknown();
+            }
+            return Ok(commands);
+        }
+        "press" | "type" | "single" => match parse_key(&words) {
+            Some(key) => Command::Press(key),
+            None => return unknown(),
+        },
+        "monitor" => match last {
+            "store" => Command::Monitor(Monitor::Store),
+            "accumulator" => Command::Monitor(Monitor::Accumulator),
+            "control" => Command::Monitor(Monitor::Control),
+            _ => return unknown(),
+        },
+        "load" => {
+            let file = original
+                .iter()
+                .skip(1)
+                .map(|w| w.trim_end_matches('.'))
+                .find(|w| {
+                    let w = w.to_lowercase();
+                    w.ends_with(".snp") || w.ends_with(".asm")
+                });
+            match file {
+                Some(file) => Command::Load(PathBuf::from(file)),
+                None => return unknown(),
+            }
+        }
+        "run" if words.len() == 1 => Command::Run,
+        "stop" if words.len() == 1 => Command::SetRunning(false),
+        "show" | "display" => Command::Show,
+        _ => return unknown(),
+    };
+    commands.push(command);
+    Ok(commands)
+}
+
+/// Reads the key of "press KC", "press the Kc switch", "single shot", "type 5" or "press typewriter key 5"
+fn parse_key(words: &[&str]) -> Option<Key> {
+    if words.contains(&"single") || words.contains(&"single-shot") {
+        return Some(Key::SingleShot);
+    }
+    if words[0] == "type" || words.contains(&"typewriter") {
+        let bit = words.last()?.parse::<u32>().ok()?;
+        return (bit < 32).then_some(Key::Typewriter(bit));
+    }
+    words.iter().find_map(|word| match *word {
+        "kc" => Some(Key::KC),
+        "ksc" => Some(Key::KSC),
+        "klc" => Some(Key::KLC),
+        "kac" => Some(Key::KAC),
+        _ => None,
+    })
+}
+
+/// Operator procedure held in the comments of a program file, or the whole file for other scripts
+pub fn script_from_file(path: &Path) -> io::Result<String> {
+    let content = std::fs::read_to_string(path)?;
+    let is_program = path
+        .extension()
+        .is_some_and(|ext| ext == "snp" || ext == "asm");
+    if !is_program {
+        return Ok(content);
+    }
+    Ok(content
+        .lines()
+        .filter(|line| line.trim_start().starts_with(';'))
+        .map(|line| format!("{line}\n"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::ssem::profile::Profile;
+    use crate::ssem::simulator::Simulator;
+
+    use super::{parse_line, script_from_file, Command, FrontPanel, Key, Mode, Monitor};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_line(";   3) Set Line (L) switches to 31. (All switches down)"),
+            Ok(vec![Command::SetLine(31)])
+        );
+        assert_eq!(
+            parse_line("; Set Function (F) switches to Fn7. (All switches down)."),
+            Ok(vec![Command::SetFunction(7)])
+        );
+        assert_eq!(
+            parse_line(";   6) Set Read /Write switch to Write."),
+            Ok(vec![Command::SetWrite(true)])
+        );
+        assert_eq!(
+            parse_line(";   2) Clear the Store, Accumulator and Control."),
+            Ok(vec![
+                Command::Press(Key::KSC),
+                Command::Press(Key::KAC),
+                Command::Press(Key::KC)
+            ])
+        );
+        assert_eq!(
+            parse_line("; RUN (If the Stop light is lit press the Kc switch first)."),
+            Ok(vec![Command::PressIfStopped(Key::KC), Command::Run])
+        );
+        assert_eq!(
+            parse_line("; Load JMP1Test.snp from the P.C."),
+            Ok(vec![Command::Load("JMP1Test.snp".into())])
+        );
+        assert_eq!(
+            parse_line("; Monitor the Store."),
+            Ok(vec![Command::Monitor(Monitor::Store)])
+        );
+        assert_eq!(
+            parse_line("press typewriter key 4"),
+            Ok(vec![Command::Press(Key::Typewriter(4))])
+        );
+        assert_eq!(parse_line("; This loads 1010101 etc."), Ok(vec![]));
+        assert!(parse_line("Set Line switches to 40").is_err());
+    }
+
+    #[test]
+    fn manual() {
+        let mut panel = FrontPanel::new(Simulator::new(), 100);
+        panel.press(Key::KSC);
+
+        // Type the number 5 into line 20, then LDN 20 by hand
+        panel.line_switches = 20;
+        panel.press(Key::Typewriter(0));
+        panel.press(Key::Typewriter(2));
+        assert_eq!(panel.simulator.store[20], 5);
+        panel.press(Key::Typewriter(2));
+        assert_eq!(panel.simulator.store[20], 1);
+        panel.press(Key::Typewriter(2));
+        assert_eq!(panel.simulator.store[20], 5);
+        panel.mode = Mode::Manual;
+        panel.function_switches = 2;
+        panel.press(Key::SingleShot);
+        assert_eq!(panel.simulator.a, -5);
+        assert_eq!(panel.simulator.ci, 1);
+
+        panel.press(Key::KLC);
+        panel.press(Key::KAC);
+        assert_eq!((panel.simulator.store[20], panel.simulator.a), (0, 0));
+
+        // STP lights the Stop light, which prevents running until KC
+        panel.function_switches = 7;
+        panel.press(Key::SingleShot);
+        assert!(panel.stop_light());
+        assert_eq!(panel.set_running(true), 0);
+        panel.press(Key::KC);
+        assert!(!panel.stop_light());
+        assert_eq!((panel.simulator.ci, panel.simulator.next_address()), (0, 1));
+
+        // The machine starts from line 1 after KC whatever the profile
+        panel.simulator.set_profile(Profile::SHARP);
+        panel.press(Key::KC);
+        assert_eq!(panel.simulator.next_address(), 1);
+    }
+
+    #[test]
+    fn ccs_tests() {
+        let directory = Path::new("samples/ssem/tests");
+        let run = |name: &str| {
+            let mut panel = FrontPanel::new(Simulator::new(), 10_000);
+            let script = script_from_file(&directory.join(name)).unwrap();
+            let mut output = Vec::new();
+            panel.run_script(&script, directory, &mut output).unwrap();
+            (panel, String::from_utf8(output).unwrap())
+        };
+
+        let (panel, output) = run("ALL1Test.snp");
+        assert!(panel.stop_light());
+        assert_eq!(panel.simulator.store[16], -1);
+        assert!(output.contains("> RUN (If the Stop light is lit press the Kc switch first)."));
+
+        let (panel, output) = run("JMP1Test.snp");
+        assert!(!panel.stop_light());
+        assert_eq!(panel.monitor, Monitor::Control);
+        assert!(output.ends_with(" PI\n"));
+
+        for name in [
+            "CMP1Test.snp",
+            "CMP2Test.snp",
+            "STO1Test.snp",
+            "STO2Test.snp",
+        ] {
+            assert!(run(name).0.stop_light(), "{name} should stop");
+        }
+        for name in ["JRP1Test.snp", "LDN1Test.snp", "SUB1Test.snp"] {
+            assert!(!run(name).0.stop_light(), "{name} should keep running");
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Memory-mapped devices: store lines whose reads and writes are handled by a peripheral instead of the store
+//!
+//! The machine has no I/O instructions, peripherals give it some without changing the instruction set. A line
+//! claimed by a peripheral gets its value from it whenever an instruction reads it (LDN, SUB, JMP, JRP), and the
+//! peripheral is told about every STO to it.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use super::teleprinter;
+
+/// A device mapped onto store lines, see `Simulator::attach_peripheral`
+pub trait Peripheral {
+    /// Value read by an instruction at the given claimed address, during the given cycle
+    fn read(&mut self, address: i32, cycle: u64) -> i32;
+
+    /// An instruction stored `value` at the given claimed address
+    fn write(&mut self, address: i32, value: i32, cycle: u64);
+}
+
+/// Reads a number from each line of its input. Gives 0 once the input is exhausted.
+pub struct NumberInput<R: BufRead> {
+    input: R,
+}
+
+impl<R: BufRead> NumberInput<R> {
+    pub fn new(input: R) -> NumberInput<R> {
+        NumberInput { input }
+    }
+}
+
+impl<R: BufRead> Peripheral for NumberInput<R> {
+    fn read(&mut self, address: i32, _cycle: u64) -> i32 {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => return 0,
+                Ok(_) => match line.trim().parse() {
+                    Ok(value) => return value,
+                    Err(_) => {
+                        eprintln!("Line {address} expects a number, '{}' ignored", line.trim())
+                    }
+                },
+            }
+        }
+    }
+
+    fn write(&mut self, _address: i32, _value: i32, _cycle: u64) {}
+}
+
+/// Writes every number stored to it on a line of its output. Reads give the last number written.
+pub struct NumberOutput<W: Write> {
+    output: W,
+    last: i32,
+}
+
+impl<W: Write> NumberOutput<W> {
+    pub fn new(output: W) -> NumberOutput<W> {
+        NumberOutput { output, last: 0 }
+    }
+}
+
+impl<W: Write> Peripheral for NumberOutput<W> {
+    fn read(&mut self, _address: i32, _cycle: u64) -> i32 {
+        self.last
+    }
+
+    fn write(&mut self, _address: i32, value: i32, _cycle: u64) {
+        self.last = value;
+        if let Err(e) = writeln!(self.output, "{value}").and_then(|_| self.output.flush()) {
+            eprintln!("Unable to write the output: {e}");
+        }
+    }
+}
+
+/// Character terminal in Manchester teleprinter code: stores print the character of the 5 least significant bits,
+/// reads give the code of the next character typed. Characters outside of the code are skipped.
+pub struct Console<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+
+    /// Characters typed but not read yet
+    pending: Vec<char>,
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(input: R, output: W) -> Console<R, W> {
+        Console {
+            input,
+            output,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Peripheral for Console<R, W> {
+    fn read(&mut self, _address: i32, _cycle: u64) -> i32 {
+        loop {
+            if self.pending.is_empty() {
+                let mut line = String::new();
+                match self.input.read_line(&mut line) {
+                    Ok(0) | Err(_) => return 0,
+                    Ok(_) => self.pending = line.chars().rev().collect(),
+                }
+            }
+            while let Some(c) = self.pending.pop() {
+                if let Some(code) = teleprinter::code(c) {
+                    return code;
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, _address: i32, value: i32, _cycle: u64) {
+        let c = teleprinter::character(value);
+        if let Err(e) = write!(self.output, "{c}").and_then(|_| self.output.flush()) {
+            eprintln!("Unable to write to the console: {e}");
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Drop for Console<R, W> {
+    fn drop(&mut self) {
+        // Ends the printed line
+        writeln!(self.output).ok();
+    }
+}
+
+/// Reads give the number of cycles executed, storing a number sets the count to it
+#[derive(Debug, Default)]
+pub struct CycleCounter {
+    /// Value of the counter at cycle 0
+    offset: i64,
+}
+
+impl Peripheral for CycleCounter {
+    fn read(&mut self, _address: i32, cycle: u64) -> i32 {
+        (cycle as i64).wrapping_add(self.offset) as i32
+    }
+
+    fn write(&mut self, _address: i32, value: i32, cycle: u64) {
+        self.offset = i64::from(value) - cycle as i64;
+    }
+}
+
+/// Built-in peripherals that can be mapped from the command line
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PeripheralKind {
+    /// `NumberInput` on the standard input
+    Input,
+
+    /// `NumberOutput` on the standard output
+    Output,
+
+    /// `Console` on the standard input and output
+    Console,
+
+    /// `CycleCounter`
+    Cycles,
+}
+
+impl PeripheralKind {
+    /// A new peripheral of this kind, on the standard input and output
+    pub fn create(&self) -> Box<dyn Peripheral> {
+        match self {
+            PeripheralKind::Input => Box::new(NumberInput::new(io::stdin().lock())),
+            PeripheralKind::Output => Box::new(NumberOutput::new(io::stdout())),
+            PeripheralKind::Console => Box::new(Console::new(io::stdin().lock(), io::stdout())),
+            PeripheralKind::Cycles => Box::<CycleCounter>::default(),
+        }
+    }
+}
+
+impl fmt::Display for PeripheralKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeripheralKind::Input => write!(f, "input"),
+            PeripheralKind::Output => write!(f, "output"),
+            PeripheralKind::Console => write!(f, "console"),
+            PeripheralKind::Cycles => write!(f, "cycles"),
+        }
+    }
+}
+
+/// A store line given to a built-in peripheral, parsed from `ADDR=KIND`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mapping {
+    pub address: i32,
+    pub kind: PeripheralKind,
+}
+
+impl FromStr for Mapping {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Mapping, Self::Err> {
+        let invalid = || {
+            format!("invalid mapping '{input}', expected ADDR=KIND with KIND input, output, console or cycles")
+        };
+        let (address, kind) = input.split_once('=').ok_or_else(invalid)?;
+        let kind = match kind.trim().to_ascii_lowercase().as_str() {
+            "input" => PeripheralKind::Input,
+            "output" => PeripheralKind::Output,
+            "console" => PeripheralKind::Console,
+            "cycles" => PeripheralKind::Cycles,
+            _ => return Err(invalid()),
+        };
+        Ok(Mapping {
+            address: address.trim().parse().map_err(|_| invalid())?,
+            kind,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    use crate::ssem::simulator::{Simulator, StopReason};
+    use crate::ssem::testing::{SharedOutput, TempDir};
+
+    use super::{Console, CycleCounter, Mapping, NumberInput, NumberOutput, PeripheralKind};
+
+    fn program(name: &str, source: &str) -> Simulator {
+        let dir = TempDir::new();
+        Simulator::from_file(&dir.write(&format!("{name}.asm"), source))
+    }
+
+    #[test]
+    fn numbers() {
+        // Adds two numbers read from line 30, then outputs the sum and the cycle counter on line 31
+        let mut simulator = program(
+            "numbers",
+            "00 NUM 0\n01 LDN 30\n02 SUB 30\n03 STO 28\n04 LDN 28\n05 STO 31\n06 LDN 27\n07 STO 31\n08 STP\n",
+        );
+        let output = SharedOutput::default();
+        simulator
+            .attach_peripheral(
+                &[30],
+                Box::new(NumberInput::new(Cursor::new("3\nfour\n4\n"))),
+            )
+            .unwrap();
+        simulator
+            .attach_peripheral(&[31], Box::new(NumberOutput::new(output.clone())))
+            .unwrap();
+        simulator
+            .attach_peripheral(&[27], Box::<CycleCounter>::default())
+            .unwrap();
+        assert!(simulator
+            .attach_peripheral(&[31], Box::<CycleCounter>::default())
+            .is_err());
+
+        simulator.run(100);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!(output.text(), "7\n-5\n");
+    }
+
+    #[test]
+    fn console() {
+        // Echoes the characters typed, two of them in 10 cycles
+        let mut simulator = program(
+            "console",
+            "00 NUM 0\n01 LDN 31\n02 STO 30\n03 LDN 30\n04 STO 31\n05 JMP 0\n",
+        );
+        let output = SharedOutput::default();
+        simulator
+            .attach_peripheral(
+                &[31],
+                Box::new(Console::new(Cursor::new("hi\n"), output.clone())),
+            )
+            .unwrap();
+        simulator.run(10);
+        drop(simulator.detach_peripherals());
+        assert_eq!(output.text(), "HI\n");
+    }
+
+    #[test]
+    fn mapping() {
+        assert_eq!(
+            Mapping::from_str("31=Output"),
+            Ok(Mapping {
+                address: 31,
+                kind: PeripheralKind::Output
+            })
+        );
+        assert!(Mapping::from_str("31=printer").is_err());
+    }
+}
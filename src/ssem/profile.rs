@@ -0,0 +1,193 @@
+//! Compatibility profiles, for the details on which implementations of the machine disagree
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Behaviour of the machine on points where the original, its replica and other simulators differ.
+///
+/// Arithmetic is not part of it: every implementation works on 32-bit two's complement numbers and lets LDN and
+/// SUB wrap around silently, as the hardware had no way to signal an overflow.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Profile {
+    /// CI is incremented before fetching the instruction, so it holds the address of the instruction being
+    /// executed. Otherwise it is incremented after the fetch and holds the address of the next instruction.
+    ///
+    /// Programs run the same either way: jumps land on the line after the one held in the store.
+    pub increment_before_fetch: bool,
+
+    /// Added to CI by JRP on top of the value held in the store
+    pub jrp_offset: i32,
+
+    /// CMP skips the next instruction when the accumulator is zero, not only when it is negative
+    pub cmp_skips_on_zero: bool,
+
+    /// CI is left pointing at the STP instruction once the machine stops, instead of the following line
+    pub stop_ci_on_stp: bool,
+}
+
+impl Profile {
+    /// The 1948 machine
+    pub const ORIGINAL: Profile = Profile {
+        increment_before_fetch: true,
+        jrp_offset: 0,
+        cmp_skips_on_zero: false,
+        stop_ci_on_stp: true,
+    };
+
+    /// The 1998 replica, built to reproduce the logic of the original, except that CI moves past STP once the
+    /// machine stops
+    pub const REPLICA: Profile = Profile {
+        increment_before_fetch: true,
+        jrp_offset: 0,
+        cmp_skips_on_zero: false,
+        stop_ci_on_stp: false,
+    };
+
+    /// David Sharp's simulator, showing in CI the address of the next instruction, with JRP landing one line further
+    /// and CMP skipping on zero too
+    pub const SHARP: Profile = Profile {
+        increment_before_fetch: false,
+        jrp_offset: 1,
+        cmp_skips_on_zero: true,
+        stop_ci_on_stp: false,
+    };
+
+    /// Address of the instruction fetched next, given the value of CI
+    pub fn next_address(&self, ci: i32, store_size: i32) -> i32 {
+        if self.increment_before_fetch {
+            (ci + 1) % store_size
+        } else {
+            ci % store_size
+        }
+    }
+
+    /// Value of CI making the machine fetch the given address next, the opposite of `next_address`
+    pub fn ci_for(&self, next_address: i32, store_size: i32) -> i32 {
+        if self.increment_before_fetch {
+            (next_address + store_size - 1) % store_size
+        } else {
+            next_address
+        }
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::ORIGINAL
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    /// Parses a profile name: `1948`, `1998` or `sharp`
+    fn from_str(input: &str) -> Result<Profile, Self::Err> {
+        match input {
+            "1948" | "original" => Ok(Profile::ORIGINAL),
+            "1998" | "replica" => Ok(Profile::REPLICA),
+            "sharp" => Ok(Profile::SHARP),
+            _ => Err(format!(
+                "unknown profile '{input}', expected 1948, 1998 or sharp"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CI incremented {} fetch, JRP offset {:+}, CMP {} on zero, CI {} STP once stopped",
+            if self.increment_before_fetch {
+                "before"
+            } else {
+                "after"
+            },
+            self.jrp_offset,
+            if self.cmp_skips_on_zero {
+                "skips"
+            } else {
+                "doesn't skip"
+            },
+            if self.stop_ci_on_stp { "on" } else { "after" },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::ssem::simulator::{Simulator, StopReason};
+    use crate::ssem::testing::TempDir;
+
+    use super::Profile;
+
+    const PROFILES: [Profile; 3] = [Profile::ORIGINAL, Profile::REPLICA, Profile::SHARP];
+
+    /// Runs the given program until it stops
+    fn run(profile: Profile, source: &str) -> Simulator {
+        let dir = TempDir::new();
+        let mut simulator = Simulator::from_file(&dir.write("profile.asm", source));
+        simulator.set_profile(profile);
+        simulator.run(100);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        simulator
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Profile::from_str("1948"), Ok(Profile::ORIGINAL));
+        assert_eq!(Profile::from_str("original"), Ok(Profile::ORIGINAL));
+        assert_eq!(Profile::from_str("1998"), Ok(Profile::REPLICA));
+        assert_eq!(Profile::from_str("replica"), Ok(Profile::REPLICA));
+        assert_eq!(Profile::from_str("sharp"), Ok(Profile::SHARP));
+        assert!(Profile::from_str("1949").is_err());
+        assert!(Profile::from_str("").is_err());
+
+        // Every profile behaves differently
+        assert_ne!(Profile::ORIGINAL, Profile::REPLICA);
+        assert_ne!(Profile::REPLICA, Profile::SHARP);
+        assert_ne!(Profile::ORIGINAL, Profile::SHARP);
+    }
+
+    #[test]
+    fn increment_before_fetch() {
+        // CI holds the address of the instruction executed, or of the next one
+        let dir = TempDir::new();
+        let path = dir.write("increment.asm", "01 LDN 0\n02 STP\n");
+        for (profile, ci) in PROFILES.into_iter().zip([1, 1, 2]) {
+            let mut simulator = Simulator::from_file(&path);
+            simulator.set_profile(profile);
+            simulator.step();
+            assert_eq!((simulator.ci, simulator.next_address()), (ci, 2));
+        }
+    }
+
+    #[test]
+    fn jrp_offset() {
+        // Jumps 1 line on from line 1: lands on line 3 with no offset, on line 4 otherwise
+        let source =
+            "01 JRP 6\n02 STP\n03 LDN 7\n04 SUB 8\n05 STP\n06 NUM 1\n07 NUM 10\n08 NUM 1\n";
+        for (profile, a) in PROFILES.into_iter().zip([-11, -11, -1]) {
+            assert_eq!(run(profile, source).a, a);
+        }
+    }
+
+    #[test]
+    fn cmp_skips_on_zero() {
+        // A is 0 when tested
+        let source = "01 CMP\n02 STP\n03 LDN 5\n04 STP\n05 NUM -7\n";
+        for (profile, a) in PROFILES.into_iter().zip([0, 0, 7]) {
+            assert_eq!(run(profile, source).a, a);
+        }
+    }
+
+    #[test]
+    fn stop_ci_on_stp() {
+        for (profile, ci) in PROFILES.into_iter().zip([1, 2, 2]) {
+            let simulator = run(profile, "01 STP\n");
+            assert_eq!(simulator.ci, ci);
+        }
+    }
+}
@@ -1,145 +1,890 @@
-use std::{fmt, path::Path};
+use std::{
+    fmt,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use super::{opcode::Opcode, store::Store};
+use rustc_hash::FxHashSet;
+
+use super::{
+    faults::{Fault, FaultInjector, FaultOptions},
+    heatmap::AccessCounts,
+    history::{History, UndoEntry},
+    interrupt::Interrupt,
+    loop_detector::LoopDetector,
+    opcode::Opcode,
+    overflow::{Overflow, OverflowMode},
+    peripheral::Peripheral,
+    profile::Profile,
+    store::{SourceMap, Store},
+    uninitialized::UninitializedRead,
+};
+
+/// Number of overflows kept by the log, further ones are only counted
+const MAX_LOGGED_OVERFLOWS: usize = 1000;
+
+/// Longest run replayed to find where a detected loop starts, about a second
+const MAX_LOOP_REPLAY_CYCLES: u64 = 20_000_000;
+
+/// Why the machine stopped running
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopReason {
+    /// The STP instruction was executed
+    Halted,
+
+    /// The cycle budget given to `run` is exhausted
+    MaxCycles,
+
+    /// The next instruction to execute is at a breakpoint address
+    Breakpoint(i32),
+
+    /// The last executed instruction wrote to a watched address
+    Watchpoint(i32),
+
+    /// The word at the given address could not be decoded
+    InvalidInstruction(i32),
+
+    /// The machine went back to a state it had already been in, it would repeat the same `period` cycles forever.
+    /// `first_seen_cycle` is the first cycle of the loop, or only the cycle at which the repeated state was first
+    /// reached when the loop was detected too long after detection started to replay the run.
+    Loop { period: u64, first_seen_cycle: u64 },
+
+    /// The arithmetic instruction at the given address overflowed, while overflows are trapped
+    Overflow(i32),
+
+    /// A stop was requested through the `Interrupt` of the machine
+    Interrupted,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Halted => write!(f, "halted"),
+            StopReason::MaxCycles => write!(f, "maximum amount of cycles reached"),
+            StopReason::Breakpoint(address) => write!(f, "breakpoint at address {address}"),
+            StopReason::Watchpoint(address) => write!(f, "write to watched address {address}"),
+            StopReason::InvalidInstruction(address) => {
+                write!(f, "invalid instruction at address {address}")
+            }
+            StopReason::Loop {
+                period,
+                first_seen_cycle,
+            } => write!(
+                f,
+                "infinite loop of {period} cycles, starting at cycle {first_seen_cycle}"
+            ),
+            StopReason::Overflow(address) => {
+                write!(f, "arithmetic overflow at address {address}")
+            }
+            StopReason::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// Details about an instruction that was just executed
+#[derive(Debug, Copy, Clone)]
+pub struct ExecutedInstruction {
+    /// Number of the cycle, starting from 0
+    pub cycle: u64,
+
+    /// Address of the instruction (value of CI during its execution)
+    pub address: i32,
+
+    /// Raw word of the instruction
+    pub word: i32,
+
+    pub opcode: Opcode,
+    pub operand: i32,
+
+    /// Word read from the store at the operand address, for instructions that read one
+    pub operand_value: Option<i32>,
+
+    pub a_before: i32,
+    pub a_after: i32,
+
+    /// Value of CI after the execution, before the next fetch
+    pub ci_after: i32,
+
+    /// Address of the instruction fetched next
+    pub next_address: i32,
+}
+
+/// Gets notified of every instruction executed by a `Simulator`
+pub trait Observer {
+    fn instruction_executed(&mut self, instruction: &ExecutedInstruction, store: &Store);
+}
 
 pub struct Simulator {
     /// Accumulator, the only register of the machine
     pub a: i32,
 
-    /// Program counter. this points to the address currently being executed
+    /// Program counter. this points to the address currently being executed, or the next one depending on the
+    /// profile
     pub ci: i32,
 
     /// The main memory. This is an array of 32-bit words
     pub store: Store,
 
-    /// This is set when the STP instruction is executed
-    stop_flag: bool,
+    /// Number of instructions executed since the machine was initialized
+    pub cycles: u64,
+
+    /// `run` stops before executing an instruction at one of these addresses
+    pub breakpoints: FxHashSet<i32>,
+
+    /// `run` stops after an instruction writes to one of these addresses
+    pub watchpoints: FxHashSet<i32>,
+
+    /// Details of the behaviour of the machine, the 1948 original by default
+    profile: Profile,
+
+    /// Whether LDN and SUB check their results fit in 32 bits
+    pub overflow_mode: OverflowMode,
+
+    /// Overflows detected so far, the first `MAX_LOGGED_OVERFLOWS` of them
+    overflows: Vec<Overflow>,
+    overflow_count: u64,
+
+    /// Whether each word was set by the loaded file or by STO, only when uninitialized reads are tracked
+    initialized: Option<Vec<bool>>,
+    uninitialized_reads: Vec<UninitializedRead>,
+
+    /// Only set while accesses to the store are counted
+    access_counts: Option<Box<AccessCounts>>,
+
+    /// Only set while faults are injected
+    fault_injector: Option<Box<FaultInjector>>,
+
+    /// Devices mapped onto store lines, and which of them claimed each line
+    peripherals: Vec<Box<dyn Peripheral>>,
+    peripheral_claims: Vec<Option<usize>>,
+
+    /// Checked before each cycle of `run`, to stop it from outside. The pending flag is kept at hand as it is
+    /// loaded at every cycle.
+    interrupt: Option<(Arc<Interrupt>, Arc<AtomicBool>)>,
+
+    /// Notified after each instruction. Kept empty when possible as they slow down the execution
+    observers: Vec<Box<dyn Observer>>,
+
+    /// Undo log, only recorded when reverse execution is enabled
+    history: Option<History>,
+
+    /// Only set when infinite loop detection is enabled
+    loop_detector: Option<Box<LoopDetector>>,
+
+    /// This is set when the machine stops, for instance when the STP instruction is executed
+    stop_reason: Option<StopReason>,
 }
 
 impl Simulator {
     pub fn new() -> Simulator {
+        Simulator::with_store(Store::new())
+    }
+
+    fn with_store(store: Store) -> Simulator {
         Simulator {
             a: 0,
             ci: 0,
-            store: Store::new(),
-            stop_flag: false,
+            store,
+            cycles: 0,
+            breakpoints: FxHashSet::default(),
+            watchpoints: FxHashSet::default(),
+            profile: Profile::default(),
+            overflow_mode: OverflowMode::default(),
+            overflows: Vec::new(),
+            overflow_count: 0,
+            initialized: None,
+            uninitialized_reads: Vec::new(),
+            access_counts: None,
+            fault_injector: None,
+            peripherals: Vec::new(),
+            peripheral_claims: Vec::new(),
+            interrupt: None,
+            observers: Vec::new(),
+            history: None,
+            loop_detector: None,
+            stop_reason: None,
         }
     }
 
+    /// Copy of the state of the machine and of its behaviour, without the debugging and analysis facilities
+    /// (breakpoints, observers, history...)
+    pub fn fork(&self) -> Simulator {
+        let mut simulator = Simulator::with_store(Store {
+            words: self.store.words.clone(),
+            size: self.store.size,
+        });
+        simulator.a = self.a;
+        simulator.ci = self.ci;
+        simulator.cycles = self.cycles;
+        simulator.profile = self.profile;
+        simulator.overflow_mode = self.overflow_mode;
+        simulator.interrupt = self.interrupt.clone();
+        simulator
+    }
+
     /// Initializes an SSEM simulator with memory state described in the given file.
     /// Supported file types are .snp and .asm
     pub fn from_file(filename: &Path) -> Simulator {
-        let store = match filename.extension() {
-            Some(ext) => {
-                if ext == "asm" {
-                    Store::from_asm_file(filename)
-                } else if ext == "snp" {
-                    Store::from_snp_file(filename)
-                } else {
-                    panic!("Unkown file format '{}'", ext.to_str().unwrap_or(""));
-                }
-            }
-            None => panic!("Unkown file format"),
-        };
+        Simulator::from_file_mapped(filename).0
+    }
 
-        Simulator {
-            a: 0,
-            ci: 0,
-            store: store,
-            stop_flag: false,
-        }
+    /// Same as `from_file`, also returning on which line of the file each address was defined
+    pub fn from_file_mapped(filename: &Path) -> (Simulator, SourceMap) {
+        let (store, source_map) = Store::from_file_mapped(filename);
+        (Simulator::with_store(store), source_map)
     }
 
     /// Run the machine until STP is encountered or the given amount of cycles is reached.
     ///
+    /// The run also stops on breakpoints, watchpoints and interruptions, see `stop_reason` to know why it ended.
+    ///
     /// Returns the number of cycles executed.
     pub fn run(&mut self, max_cycles: u32) -> u32 {
         let mut cycles = 0u32;
-        self.stop_flag = false;
+        // A run can resume from the breakpoint the previous one stopped at
+        let mut resumed_breakpoint = match self.stop_reason.take() {
+            Some(StopReason::Breakpoint(address)) => Some(address),
+            _ => None,
+        };
 
-        while cycles < max_cycles && !self.stop_flag {
+        while cycles < max_cycles && self.stop_reason.is_none() {
+            if let Some((interrupt, pending)) = &self.interrupt {
+                if pending.load(Ordering::Relaxed) && interrupt.handle(self.cycles, self.ci, self.a)
+                {
+                    self.stop_reason = Some(StopReason::Interrupted);
+                    break;
+                }
+            }
+            if !self.breakpoints.is_empty() {
+                let address = self.next_address();
+                if resumed_breakpoint.take() != Some(address) && self.breakpoints.contains(&address)
+                {
+                    self.stop_reason = Some(StopReason::Breakpoint(address));
+                    break;
+                }
+            }
             self.instruction_cycle();
             cycles += 1;
         }
 
+        if self.stop_reason.is_none() {
+            self.stop_reason = Some(StopReason::MaxCycles);
+        }
+
         cycles
     }
 
+    /// Execute a single instruction, regardless of breakpoints.
+    ///
+    /// Returns the reason why the machine stopped, if this instruction stopped it.
+    pub fn step(&mut self) -> Option<StopReason> {
+        self.stop_reason = None;
+        self.instruction_cycle();
+        self.stop_reason
+    }
+
+    /// Why the last run ended, `None` if the machine has not run yet
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Change the behaviour of the machine. CI is adjusted so that the next instruction executed stays the same.
+    pub fn set_profile(&mut self, profile: Profile) {
+        let next = self.next_address();
+        self.profile = profile;
+        self.set_next_address(next);
+    }
+
+    /// Address of the next instruction to be executed
+    pub fn next_address(&self) -> i32 {
+        self.profile.next_address(self.ci, self.store.size)
+    }
+
+    /// Sets CI so that the next instruction executed is the one at the given address
+    pub fn set_next_address(&mut self, address: i32) {
+        self.ci = self.profile.ci_for(address, self.store.size);
+    }
+
     /// Run the next instruction.
     pub fn instruction_cycle(&mut self) {
-        // Fetch
-        self.ci += 1;
-        self.ci %= self.store.size; // CI loops back to the begining when it exceeds the store boundaries
+        if let Some(injector) = &mut self.fault_injector {
+            let address = self.profile.next_address(self.ci, self.store.size);
+            let injected = injector.faults().len();
+            injector.inject(self.cycles, address, &mut self.store);
+            if let Some(history) = &mut self.history {
+                for fault in &injector.faults()[injected..] {
+                    history.record_edit(fault.address, fault.before);
+                }
+            }
+        }
+
+        let (a, ci) = (self.a, self.ci);
+        let address = self._fetch();
 
         // Decode
-        let (opcode, data) = match self.store.decode_instruction(self.ci) {
+        let (opcode, data) = match self.store.decode_instruction(address) {
             Ok(r) => r,
             Err(message) => {
                 eprintln!(
                     "Error while decoding instruction at address {}: {}",
-                    self.ci, message
+                    address, message
                 );
-                self.stop_flag = true;
+                self.stop_reason = Some(StopReason::InvalidInstruction(address));
                 return;
             }
         };
 
+        self._complete_cycle(a, ci, address, self.store[address], opcode, data);
+    }
+
+    /// Writes a word into the store from outside the program, as the front panel or a debugger does.
+    ///
+    /// The previous word is kept in the history, if enabled, so that going back restores it.
+    pub fn write_word(&mut self, address: i32, word: i32) {
+        if let Some(history) = &mut self.history {
+            history.record_edit(address, self.store[address]);
+        }
+        self.store.words[address as usize] = word;
+    }
+
+    /// Replaces the whole store, as when a program is loaded. The history can not undo this and is cleared.
+    pub fn replace_store(&mut self, store: Store) {
+        self.store = store;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /// Run the given instruction instead of the one held in the store, as the machine does in manual mode with the
+    /// instruction set on the line and function switches. CI still moves on as in any other cycle.
+    pub fn manual_cycle(&mut self, word: i32) {
+        let (a, ci) = (self.a, self.ci);
+        let address = self._fetch();
+        let (opcode, data) = match Store::decode_word(word) {
+            Ok(r) => r,
+            Err(_) => {
+                self.stop_reason = Some(StopReason::InvalidInstruction(address));
+                return;
+            }
+        };
+        self._complete_cycle(a, ci, address, word, opcode, data);
+    }
+
+    /// Move CI on and return the address of the instruction to fetch
+    #[inline(always)]
+    fn _fetch(&mut self) -> i32 {
+        if self.profile.increment_before_fetch {
+            self.ci += 1;
+            self.ci %= self.store.size; // CI loops back to the begining when it exceeds the store boundaries
+            self.ci
+        } else {
+            let address = self.ci % self.store.size;
+            self.ci = (address + 1) % self.store.size;
+            address
+        }
+    }
+
+    /// Execute the decoded instruction, `a` and `ci` being the state of the machine before the fetch
+    #[inline(always)]
+    fn _complete_cycle(
+        &mut self,
+        a: i32,
+        ci: i32,
+        address: i32,
+        word: i32,
+        opcode: Opcode,
+        data: i32,
+    ) {
+        if let Some(history) = &mut self.history {
+            // Peripherals write the words instructions read from them
+            let write = match opcode {
+                Opcode::STO => Some((data, self.store[data])),
+                Opcode::JMP | Opcode::JRP | Opcode::LDN | Opcode::SUB | Opcode::SUB2
+                    if self
+                        .peripheral_claims
+                        .get(data as usize)
+                        .is_some_and(Option::is_some) =>
+                {
+                    Some((data, self.store[data]))
+                }
+                _ => None,
+            };
+            history.record(self.cycles, UndoEntry { a, ci, write }, &self.store);
+        }
+
+        if self.initialized.is_some() {
+            self._track_initialization(address, opcode, data);
+        }
+
+        if let Some(counts) = &mut self.access_counts {
+            counts.record(address, opcode, data);
+        }
+
+        if !self.peripherals.is_empty() {
+            self._peripheral_read(opcode, data);
+        }
+
         // Execute
-        self._execute(opcode, data);
+        if self.observers.is_empty() {
+            self._execute(opcode, data, address);
+        } else {
+            self._observed_execute(opcode, data, address, word);
+        }
+
+        if !self.peripherals.is_empty() && opcode == Opcode::STO {
+            self._peripheral_write(data);
+        }
+        self.cycles += 1;
+
+        if let Some(detector) = &mut self.loop_detector {
+            if self.stop_reason.is_none() {
+                if let Some(period) = detector.check(self.a, self.ci, &self.store) {
+                    let first_seen_cycle = self._loop_start(period);
+                    self.stop_reason = Some(StopReason::Loop {
+                        period,
+                        first_seen_cycle,
+                    });
+                }
+            }
+        }
     }
 
-    /// Modify the state of the machine according to the given instruction.
-    fn _execute(&mut self, command: Opcode, data: i32) {
+    /// Stop running when the machine goes back to a state it has already been in.
+    ///
+    /// Many programs never execute STP, this allows to stop them as soon as they start repeating themselves.
+    /// Detection starts from the current state.
+    pub fn enable_loop_detection(&mut self) {
+        self.loop_detector = Some(Box::new(LoopDetector::new(
+            self.a,
+            self.ci,
+            &self.store,
+            self.cycles,
+        )));
+    }
+
+    pub fn disable_loop_detection(&mut self) {
+        self.loop_detector = None;
+    }
+
+    /// Finds the first cycle of a loop of the given period.
+    ///
+    /// The run is replayed from the state detection started at, with a second machine `period` cycles ahead:
+    /// the loop starts once both machines are in the same state. Past `MAX_LOOP_REPLAY_CYCLES`, this gives the cycle
+    /// at which the current state was first reached instead.
+    fn _loop_start(&self, period: u64) -> u64 {
+        let Some(detector) = &self.loop_detector else {
+            return self.cycles - period;
+        };
+        if self.cycles - detector.start_cycle() > MAX_LOOP_REPLAY_CYCLES {
+            return self.cycles - period;
+        }
+        let initial = detector.initial();
+
+        let replica = || {
+            let mut simulator = Simulator::with_store(Store {
+                words: initial.words.clone(),
+                size: self.store.size,
+            });
+            simulator.a = initial.a;
+            simulator.ci = initial.ci;
+            simulator.profile = self.profile;
+            simulator
+        };
+        let (mut behind, mut ahead) = (replica(), replica());
+        for _ in 0..period {
+            ahead.instruction_cycle();
+        }
+
+        for first_seen in detector.start_cycle()..self.cycles - period {
+            if behind.a == ahead.a && behind.ci == ahead.ci && behind.store == ahead.store {
+                return first_seen;
+            }
+            behind.instruction_cycle();
+            ahead.instruction_cycle();
+        }
+
+        // The machine was modified from outside during the run, replaying it doesn't lead to the same states
+        self.cycles - period
+    }
+
+    /// Overflows detected since the machine was initialized, when `overflow_mode` isn't `Wrap`.
+    ///
+    /// Only the first ones are kept, see `overflow_count` for the total.
+    pub fn overflows(&self) -> &[Overflow] {
+        &self.overflows
+    }
+
+    pub fn overflow_count(&self) -> u64 {
+        self.overflow_count
+    }
+
+    /// Records an overflow of the instruction being executed, stopping the machine if they are trapped
+    #[cold]
+    fn _overflow(&mut self, address: i32, opcode: Opcode, operand: i32, result: i32) {
+        self.overflow_count += 1;
+        if self.overflows.len() < MAX_LOGGED_OVERFLOWS {
+            self.overflows.push(Overflow {
+                cycle: self.cycles,
+                address,
+                opcode,
+                a: self.a,
+                operand,
+                result,
+            });
+        }
+        if self.overflow_mode == OverflowMode::Trap {
+            self.stop_reason = Some(StopReason::Overflow(address));
+        }
+    }
+
+    /// Report the instructions reading words that were never set.
+    ///
+    /// Words defined by a line of the loaded file count as set, the others only once written by STO.
+    pub fn enable_uninitialized_tracking(&mut self, source_map: &SourceMap) {
+        self.initialized = Some(
+            (0..self.store.size)
+                .map(|address| source_map.line(address).is_some())
+                .collect(),
+        );
+    }
+
+    pub fn disable_uninitialized_tracking(&mut self) {
+        self.initialized = None;
+    }
+
+    /// Instructions that read a word never set, in the order they first did
+    pub fn uninitialized_reads(&self) -> &[UninitializedRead] {
+        &self.uninitialized_reads
+    }
+
+    /// Count how many times each line of the store is executed, read and written from now on
+    pub fn enable_access_counting(&mut self) {
+        self.access_counts = Some(Box::new(AccessCounts::new(self.store.size as usize)));
+    }
+
+    pub fn disable_access_counting(&mut self) {
+        self.access_counts = None;
+    }
+
+    /// Counts of the accesses since `enable_access_counting`
+    pub fn access_counts(&self) -> Option<&AccessCounts> {
+        self.access_counts.as_deref()
+    }
+
+    #[inline(never)]
+    fn _track_initialization(&mut self, address: i32, opcode: Opcode, data: i32) {
+        let Some(initialized) = &mut self.initialized else {
+            return;
+        };
+        match opcode {
+            Opcode::STO => initialized[data as usize] = true,
+            Opcode::JMP | Opcode::JRP | Opcode::LDN | Opcode::SUB | Opcode::SUB2
+                if !initialized[data as usize] =>
+            {
+                let known = self
+                    .uninitialized_reads
+                    .iter_mut()
+                    .find(|read| read.address == address && read.operand == data);
+                match known {
+                    Some(read) => read.count += 1,
+                    None => self.uninitialized_reads.push(UninitializedRead {
+                        address,
+                        opcode,
+                        operand: data,
+                        first_cycle: self.cycles,
+                        count: 1,
+                    }),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Corrupt the store during the following runs, as an unreliable Williams tube would.
+    ///
+    /// Scheduled faults refer to absolute cycle numbers, see `cycles`.
+    pub fn enable_fault_injection(&mut self, options: FaultOptions) {
+        self.fault_injector = Some(Box::new(FaultInjector::new(options)));
+    }
+
+    /// Stop injecting faults, returning the ones injected so far
+    pub fn disable_fault_injection(&mut self) -> Vec<Fault> {
+        self.fault_injector
+            .take()
+            .map(|injector| injector.into_faults())
+            .unwrap_or_default()
+    }
+
+    /// Hand the given store lines over to a peripheral: instructions reading them get their value from it, and it
+    /// is told about every STO to them. The store keeps the last value read or written.
+    ///
+    /// Fails when a line is outside the store or already claimed by another peripheral.
+    pub fn attach_peripheral(
+        &mut self,
+        addresses: &[i32],
+        peripheral: Box<dyn Peripheral>,
+    ) -> Result<(), String> {
+        self.peripheral_claims
+            .resize(self.store.size as usize, None);
+        for address in addresses {
+            if !(0..self.store.size).contains(address) {
+                return Err(format!("line {address} is outside the store"));
+            }
+            if self.peripheral_claims[*address as usize].is_some() {
+                return Err(format!("line {address} is already mapped to a peripheral"));
+            }
+        }
+
+        for address in addresses {
+            self.peripheral_claims[*address as usize] = Some(self.peripherals.len());
+        }
+        self.peripherals.push(peripheral);
+        Ok(())
+    }
+
+    /// Give the store lines back to the store and return the peripherals, so that they can be finalized
+    pub fn detach_peripherals(&mut self) -> Vec<Box<dyn Peripheral>> {
+        self.peripheral_claims.clear();
+        std::mem::take(&mut self.peripherals)
+    }
+
+    #[inline(never)]
+    fn _peripheral_read(&mut self, opcode: Opcode, data: i32) {
+        if !matches!(
+            opcode,
+            Opcode::JMP | Opcode::JRP | Opcode::LDN | Opcode::SUB | Opcode::SUB2
+        ) {
+            return;
+        }
+        if let Some(Some(index)) = self.peripheral_claims.get(data as usize) {
+            self.store.words[data as usize] = self.peripherals[*index].read(data, self.cycles);
+        }
+    }
+
+    #[inline(never)]
+    fn _peripheral_write(&mut self, data: i32) {
+        if let Some(Some(index)) = self.peripheral_claims.get(data as usize) {
+            self.peripherals[*index].write(data, self.store[data], self.cycles);
+        }
+    }
+
+    /// Let the given `Interrupt` stop the following runs or ask them for their progress
+    pub fn set_interrupt(&mut self, interrupt: Option<Arc<Interrupt>>) {
+        self.interrupt = interrupt.map(|interrupt| {
+            let pending = interrupt.pending_flag();
+            (interrupt, pending)
+        });
+    }
+
+    /// Write the store to a .snp file, with the state of the registers in a comment
+    pub fn save_snapshot(&self, path: &Path) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut output = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(
+            output,
+            "; Snapshot taken at cycle {}, CI = {}, A = {}",
+            self.cycles, self.ci, self.a
+        )?;
+        writeln!(output)?;
+        self.store.write_snp(&mut output)?;
+        output.flush()
+    }
+
+    /// Register an observer to be notified after each executed instruction
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Unregister every observer and give them back, so that they can be finalized
+    pub fn take_observers(&mut self) -> Vec<Box<dyn Observer>> {
+        std::mem::take(&mut self.observers)
+    }
+
+    /// Start recording an undo log so that the machine can be run backwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of cycles kept in the log. Memory usage is bounded by this value.
+    /// * `checkpoint_interval` - Number of cycles between two full copies of the machine. Smaller values make
+    ///   large steps back faster at the cost of memory.
+    pub fn enable_history(&mut self, capacity: usize, checkpoint_interval: u64) {
+        self.history = Some(History::new(capacity, checkpoint_interval));
+    }
+
+    /// Stop recording the undo log and free it
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Earliest cycle the machine can go back to, `None` if the history is disabled
+    pub fn history_start(&self) -> Option<u64> {
+        self.history.as_ref().map(|h| h.first_cycle())
+    }
+
+    /// Run the machine backwards by the given amount of cycles.
+    ///
+    /// Returns the number of cycles actually undone, which can be less than requested when reaching the beginning
+    /// of the recorded history.
+    pub fn step_back(&mut self, cycles: u64) -> u64 {
+        let Some(history) = &mut self.history else {
+            return 0;
+        };
+        let reached = history.rewind(
+            self.cycles.saturating_sub(cycles),
+            &mut self.a,
+            &mut self.ci,
+            &mut self.store,
+        );
+        let undone = self.cycles - reached;
+        self.cycles = reached;
+        self.stop_reason = None;
+        undone
+    }
+
+    /// Run the machine backwards until the previous breakpoint or watchpoint hit.
+    ///
+    /// The machine is left in the state just before executing the instruction at the breakpoint, or the one that
+    /// wrote to the watched address. Returns `None` if the beginning of the recorded history was reached instead.
+    pub fn reverse_continue(&mut self) -> Option<StopReason> {
+        let history = self.history.as_mut()?;
+        self.stop_reason = None;
+
+        while let Some(entry) = history.undo(&mut self.a, &mut self.ci, &mut self.store) {
+            self.cycles -= 1;
+
+            let address = self.profile.next_address(self.ci, self.store.size);
+            if self.breakpoints.contains(&address) {
+                self.stop_reason = Some(StopReason::Breakpoint(address));
+            } else if let Some((address, _)) = entry.write {
+                if self.watchpoints.contains(&address) {
+                    self.stop_reason = Some(StopReason::Watchpoint(address));
+                }
+            }
+            if self.stop_reason.is_some() {
+                return self.stop_reason;
+            }
+        }
+
+        None
+    }
+
+    /// Returns the cycle of the last instruction that wrote to the given address.
+    ///
+    /// Only the recorded history is searched: `None` means the word was not written since `history_start`.
+    pub fn last_write(&self, address: i32) -> Option<u64> {
+        self.history.as_ref()?.last_write(address)
+    }
+
+    /// Same as `_execute`, notifying the observers afterwards.
+    ///
+    /// Kept out of `instruction_cycle` so that unobserved runs stay as fast as possible.
+    #[inline(never)]
+    fn _observed_execute(&mut self, opcode: Opcode, data: i32, address: i32, word: i32) {
+        let operand_value = match opcode {
+            Opcode::JMP | Opcode::JRP | Opcode::LDN | Opcode::SUB | Opcode::SUB2 => {
+                Some(self.store[data])
+            }
+            _ => None,
+        };
+        let a_before = self.a;
+
+        self._execute(opcode, data, address);
+
+        let instruction = ExecutedInstruction {
+            cycle: self.cycles,
+            address,
+            word,
+            opcode,
+            operand: data,
+            operand_value,
+            a_before,
+            a_after: self.a,
+            ci_after: self.ci,
+            next_address: self.next_address(),
+        };
+        for observer in self.observers.iter_mut() {
+            observer.instruction_executed(&instruction, &self.store);
+        }
+    }
+
+    /// Modify the state of the machine according to the given instruction, found at the given address.
+    fn _execute(&mut self, command: Opcode, data: i32, address: i32) {
         match command {
             Opcode::JMP => {
                 self.ci = self.store[data];
+                if !self.profile.increment_before_fetch {
+                    // Jumps land on the line after the one held in the store
+                    self.ci += 1;
+                }
             }
             Opcode::JRP => {
-                self.ci += self.store[data];
+                self.ci += self.store[data] + self.profile.jrp_offset;
             }
             Opcode::LDN => {
                 // Was originally `self.a = -self.store[data];`
-                // But in some cases we want to ignore overflowing. This has no measureable performance impact.
-                // TODO: assert this is compliant with SSEM behavior
-                self.a = self.store[data].wrapping_neg();
+                // The SSEM works on 32-bit two's complement numbers and has no overflow detection: negating the
+                // smallest number gives itself back, as it does here. This has no measureable performance impact.
+                let value = self.store[data];
+                if self.overflow_mode != OverflowMode::Wrap && value == i32::MIN {
+                    self._overflow(address, command, value, value);
+                }
+                self.a = value.wrapping_neg();
             }
             Opcode::STO => {
                 // this indexing is safe as long as the data extracted earlier (a u5 for SSEM)
                 // is smaller than the number of addresses on the store (32 for SSEM)
                 self.store.words[data as usize] = self.a;
+                if !self.watchpoints.is_empty() && self.watchpoints.contains(&data) {
+                    self.stop_reason = Some(StopReason::Watchpoint(data));
+                }
             }
             Opcode::SUB | Opcode::SUB2 => {
                 // Was originally `self.a -= self.store[data];`
-                // But in some cases we want to ignore overflowing. This has no measureable performance impact.
-                // TODO: assert this is compliant with SSEM behavior
-                self.a = self.a.wrapping_add(self.store[data].wrapping_neg());
+                // Wraps around on overflow like the hardware. This has no measureable performance impact.
+                let value = self.store[data];
+                let result = self.a.wrapping_add(value.wrapping_neg());
+                if self.overflow_mode != OverflowMode::Wrap && self.a.checked_sub(value).is_none() {
+                    self._overflow(address, command, value, result);
+                }
+                self.a = result;
             }
             Opcode::CMP => {
-                if self.a < 0 {
+                if self.a < 0 || (self.profile.cmp_skips_on_zero && self.a == 0) {
                     self.ci += 1;
                 }
             }
             Opcode::STP => {
-                self.stop_flag = true;
+                self.ci = if self.profile.stop_ci_on_stp {
+                    address
+                } else {
+                    (address + 1) % self.store.size
+                };
+                self.stop_reason = Some(StopReason::Halted);
             }
             Opcode::NUM => {
                 panic!("Encountered an unexpected NUM command")
             }
         }
-
-        // println!("{} {} {}", self.ci, command, data);
-        // println!("{}", &self);
     }
 }
 
 impl From<Vec<String>> for Simulator {
     fn from(value: Vec<String>) -> Self {
-        Simulator {
-            a: 0,
-            ci: 0,
-            store: Store::from(value),
-            stop_flag: false,
-        }
+        Simulator::with_store(Store::from(value))
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator::new()
     }
 }
 
@@ -149,8 +894,224 @@ impl fmt::Display for Simulator {
         // All the computing is done on the modern order for efficiency reasons.
         writeln!(f, " {:032b} CI = {:6}", self.ci.reverse_bits(), self.ci).ok();
         writeln!(f, " {:032b} A  = {:6}", self.a.reverse_bits(), self.a).ok();
-        writeln!(f, "").ok();
+        writeln!(f).ok();
         writeln!(f, "{}", self.store).ok();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use crate::ssem::faults::{FaultOptions, ScheduledFault};
+    use crate::ssem::peripheral::CycleCounter;
+    use crate::ssem::testing::TempDir;
+
+    use super::{OverflowMode, Profile, Simulator, StopReason};
+
+    #[test]
+    fn step_back() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        simulator.enable_history(1000, 16);
+        simulator.run(100);
+        let (a, ci, words) = (simulator.a, simulator.ci, simulator.store.words.clone());
+        simulator.run(150);
+
+        assert_eq!(simulator.step_back(150), 150);
+        assert_eq!(simulator.cycles, 100);
+        assert_eq!((simulator.a, simulator.ci), (a, ci));
+        assert_eq!(simulator.store.words, words);
+
+        // Can't go further than the beginning
+        assert_eq!(simulator.step_back(1000), 100);
+        assert_eq!(
+            simulator.store,
+            Simulator::from_file(Path::new("samples/ssem/fibonacci.asm")).store
+        );
+    }
+
+    #[test]
+    fn step_back_peripheral_reads() {
+        // The loop counter of line 31 is replaced by a cycle counter, read by LDN 31 once per iteration
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        simulator.enable_history(1000, 16);
+        simulator
+            .attach_peripheral(&[31], Box::<CycleCounter>::default())
+            .unwrap();
+        simulator.run(20);
+        let words = simulator.store.words.clone();
+        simulator.run(20);
+        assert_ne!(simulator.store.words, words);
+
+        assert_eq!(simulator.step_back(20), 20);
+        assert_eq!(simulator.store.words, words);
+    }
+
+    #[test]
+    fn step_back_external_writes() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        simulator.enable_history(1000, 8);
+        simulator.enable_fault_injection(FaultOptions {
+            schedule: vec![ScheduledFault::from_str("29:3@25").unwrap()],
+            ..FaultOptions::default()
+        });
+        simulator.run(10);
+        let before = simulator.store.words.clone();
+        simulator.run(10);
+        simulator.write_word(0, 12345);
+        let edited = simulator.store.words.clone();
+        simulator.run(10);
+        assert_eq!(simulator.disable_fault_injection().len(), 1);
+
+        // Going back to the cycle of the edit keeps it, going further undoes it
+        assert_eq!(simulator.step_back(10), 10);
+        assert_eq!(simulator.store.words, edited);
+        assert_eq!(simulator.step_back(10), 10);
+        assert_eq!(simulator.store.words, before);
+
+        // Same through the undo log alone, without checkpoints
+        simulator.write_word(0, 54321);
+        simulator.run(20);
+        assert_eq!(simulator.reverse_continue(), None);
+        assert_eq!(
+            simulator.store,
+            Simulator::from_file(Path::new("samples/ssem/fibonacci.asm")).store
+        );
+    }
+
+    #[test]
+    fn reverse_continue() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        simulator.enable_history(1000, 16);
+        simulator.watchpoints.insert(27);
+        // Line 17 is reached after 16 cycles as line 8 is skipped, then the loop is 17 instructions long
+        assert_eq!(simulator.run(1000), 16);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Watchpoint(27)));
+        simulator.watchpoints.clear();
+        simulator.run(50);
+
+        assert_eq!(simulator.last_write(27), Some(49));
+        assert_eq!(simulator.reverse_continue(), None);
+        assert_eq!(simulator.cycles, 0);
+
+        simulator.run(66);
+        simulator.watchpoints.insert(27);
+        assert_eq!(
+            simulator.reverse_continue(),
+            Some(StopReason::Watchpoint(27))
+        );
+        assert_eq!(simulator.cycles, 49);
+        simulator.watchpoints.clear();
+
+        simulator.breakpoints.insert(1);
+        assert_eq!(
+            simulator.reverse_continue(),
+            Some(StopReason::Breakpoint(1))
+        );
+        assert_eq!(simulator.cycles, 34);
+    }
+
+    #[test]
+    fn profiles() {
+        let run = |profile| {
+            let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+            simulator.set_profile(profile);
+            simulator.run(10_000);
+            assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+            simulator
+        };
+        let (original, replica, sharp) = (
+            run(Profile::ORIGINAL),
+            run(Profile::REPLICA),
+            run(Profile::SHARP),
+        );
+
+        // Same program behaviour, but CI shows the line after STP, from which a restart would move on
+        assert_eq!(replica.cycles, original.cycles);
+        assert_eq!(replica.store, original.store);
+        assert_eq!((original.ci, replica.ci), (8, 9));
+        assert_eq!(replica.next_address(), original.next_address() + 1);
+
+        // CMP skipping on zero, the counter reaching the limit no longer stops the loop, the next iteration does
+        assert_eq!(sharp.store[31], original.store[31] + 1);
+    }
+
+    #[test]
+    fn overflow() {
+        // Fibonacci number 47 doesn't fit in 32 bits
+        let fibonacci = |mode| {
+            let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+            simulator.store.words[29] = 50;
+            simulator.overflow_mode = mode;
+            simulator.run(10_000);
+            simulator
+        };
+
+        let simulator = fibonacci(OverflowMode::Wrap);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!(simulator.overflow_count(), 0);
+
+        let simulator = fibonacci(OverflowMode::Log);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        assert_eq!(simulator.overflow_count(), 1);
+        let overflow = simulator.overflows()[0];
+        assert_eq!((overflow.cycle, overflow.address), (773, 10));
+        assert_eq!((overflow.a, overflow.operand), (-1836311903, 1134903170));
+
+        let simulator = fibonacci(OverflowMode::Trap);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Overflow(10)));
+        assert_eq!(simulator.cycles, 774);
+        assert_eq!(simulator.a, 1323752223);
+    }
+
+    #[test]
+    fn uninitialized_reads() {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "uninitialized.asm",
+            "00 NUM 0\n01 LDN 20\n02 STO 21\n03 SUB 21\n04 STP\n",
+        );
+        let (mut simulator, source_map) = Simulator::from_file_mapped(&path);
+
+        simulator.store.randomize_unspecified(&source_map, 1948);
+        assert_eq!(
+            &simulator.store.words[..5],
+            &[0, 16404, 24597, 32789, 57344]
+        );
+        assert!(simulator.store.words[5..].iter().any(|word| *word != 0));
+
+        simulator.enable_uninitialized_tracking(&source_map);
+        simulator.run(10);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+        let reads = simulator.uninitialized_reads();
+        assert_eq!(reads.len(), 1);
+        assert_eq!((reads[0].address, reads[0].operand), (1, 20));
+    }
+
+    #[test]
+    fn loop_detection() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/nightmare.snp"));
+        let initial = simulator.fork();
+        simulator.enable_loop_detection();
+        simulator.run(100_000);
+        let Some(StopReason::Loop {
+            period,
+            first_seen_cycle,
+        }) = simulator.stop_reason()
+        else {
+            panic!("no loop detected");
+        };
+        assert_eq!((period, first_seen_cycle), (4125, 13));
+
+        // The state at the start of the loop comes back a period later, the one before it doesn't
+        let state_after = |cycles: u64| {
+            let mut replay = initial.fork();
+            replay.run(cycles as u32);
+            (replay.a, replay.ci, replay.store)
+        };
+        assert_eq!(state_after(13), state_after(13 + 4125));
+        assert_ne!(state_after(12), state_after(12 + 4125));
+    }
+}
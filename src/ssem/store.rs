@@ -1,11 +1,13 @@
 use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Write};
 use std::ops::Index;
 use std::path::Path;
 use std::str::FromStr;
 
 use super::opcode::Opcode;
+use super::random::Random;
+use super::teleprinter;
 
 const ASM_COMMENT_CHAR: char = ';';
 const SSEM_STORE_WORDS: i32 = 32;
@@ -23,6 +25,34 @@ pub struct Store {
     pub size: i32,
 }
 
+/// Links store addresses to the lines of the file they were loaded from
+#[derive(Debug, Default, Clone)]
+pub struct SourceMap {
+    /// Line number (starting from 1) of each address
+    lines: Vec<Option<usize>>,
+}
+
+impl SourceMap {
+    /// Line on which the given address was defined
+    pub fn line(&self, address: i32) -> Option<usize> {
+        *self.lines.get(usize::try_from(address).ok()?)?
+    }
+
+    /// Address defined on the given line
+    pub fn address(&self, line: usize) -> Option<i32> {
+        let address = self.lines.iter().position(|l| *l == Some(line))?;
+        i32::try_from(address).ok()
+    }
+
+    fn insert(&mut self, address: i32, line: usize) {
+        let address = address as usize;
+        if self.lines.len() <= address {
+            self.lines.resize(address + 1, None);
+        }
+        self.lines[address] = Some(line);
+    }
+}
+
 impl Store {
     /// Instanciate a new store.
     ///
@@ -36,6 +66,39 @@ impl Store {
         store
     }
 
+    /// Fills the words that no line of the loaded file defined with pseudo-random noise.
+    ///
+    /// A Williams tube doesn't start blank, this reveals programs that rely on unset words being zero. The same
+    /// seed always gives the same contents.
+    pub fn randomize_unspecified(&mut self, source_map: &SourceMap, seed: u64) {
+        let mut random = Random::new(seed);
+        for (address, word) in (0..).zip(self.words.iter_mut()) {
+            let noise = random.next_word();
+            if source_map.line(address).is_none() {
+                *word = noise;
+            }
+        }
+    }
+
+    /// Initializes the store with the given file, in .asm or .snp format depending on its extension. Also
+    /// returns on which line of the file each address was defined.
+    pub fn from_file_mapped(filename: &Path) -> (Store, SourceMap) {
+        match filename.extension() {
+            Some(ext) => {
+                if ext == "asm" {
+                    Store::from_asm_file_mapped(filename)
+                } else if ext == "snp" {
+                    Store::from_snp_file_mapped(filename)
+                } else if ext == "tp" {
+                    Store::from_tp_file_mapped(filename)
+                } else {
+                    panic!("Unkown file format '{}'", ext.to_str().unwrap_or(""));
+                }
+            }
+            None => panic!("Unkown file format"),
+        }
+    }
+
     /// Initializes the store with the given assembly file
     ///
     /// An assembly file has the following form:
@@ -47,10 +110,17 @@ impl Store {
     /// ```
     /// Each numbered line represents a word with its instruction. Erverything after ';' is ignored.
     ///
+    /// Operands starting with `#` are written in teleprinter code, e.g. `NUM #E//B` as in period listings.
+    ///
     /// # Arguments
     ///
     /// * `filename` - Path to the file to read
     pub fn from_asm_file(filename: &Path) -> Store {
+        Store::from_asm_file_mapped(filename).0
+    }
+
+    /// Same as `from_asm_file`, also returning on which line each address was defined
+    pub fn from_asm_file_mapped(filename: &Path) -> (Store, SourceMap) {
         let file = File::open(filename);
         let file = match file {
             Ok(f) => f,
@@ -69,9 +139,10 @@ impl Store {
             size: SSEM_STORE_WORDS,
         };
 
+        let mut source_map = SourceMap::default();
         let mut last_index: i32 = 0;
 
-        for (_, line) in reader.lines().enumerate() {
+        for (line_number, line) in reader.lines().enumerate() {
             let line = line.unwrap_or_else(|e| {
                 panic!(
                     "Error while reading '{}': {}",
@@ -82,9 +153,9 @@ impl Store {
 
             // Ignoring comments
             let exploded_line: Vec<&str> = line.splitn(2, ASM_COMMENT_CHAR).collect();
-            if exploded_line.len() > 0 {
-                let instruction = exploded_line[0].clone().trim();
-                if instruction.len() == 0 {
+            if !exploded_line.is_empty() {
+                let instruction = exploded_line[0].trim();
+                if instruction.is_empty() {
                     continue;
                 }
                 // Extracting tokens "<index> <opcode> <operand>"
@@ -98,12 +169,15 @@ impl Store {
 
                 let index: i32 = i[0].parse().expect("Unable to read the number");
                 let opcode: &str = i[1];
-                let operand: i32;
-                if i.len() >= 3 {
-                    operand = i[2].parse().expect("Unable to read the number");
+                let operand: i32 = if i.len() >= 3 {
+                    match i[2].strip_prefix('#') {
+                        Some(characters) => teleprinter::parse_word(characters)
+                            .unwrap_or_else(|e| panic!("Error near line '{}': {}", instruction, e)),
+                        None => i[2].parse().expect("Unable to read the number"),
+                    }
                 } else {
-                    operand = 0;
-                }
+                    0
+                };
 
                 // Ensure lines are contiguous and without duplicates
                 if index > 0 && index != last_index + 1 {
@@ -123,6 +197,8 @@ impl Store {
                     );
                 }
 
+                source_map.insert(index, line_number + 1);
+
                 let opcode = Opcode::from_str(opcode).unwrap_or_else(|_| {
                     panic!(
                         "Error near line '{}': opcode '{}' non valid.",
@@ -140,7 +216,7 @@ impl Store {
                         let w: i32 = (opcode as i32) << SSEM_OPCODE_BIT_SHIFT;
 
                         // Print the operand
-                        store.words[usize::try_from(index).unwrap()] = w | (operand as i32);
+                        store.words[usize::try_from(index).unwrap()] = w | operand;
                     }
                 }
             }
@@ -148,7 +224,7 @@ impl Store {
 
         store._check();
 
-        store
+        (store, source_map)
     }
 
     /// Initializes the store with the given snp file
@@ -166,6 +242,11 @@ impl Store {
     ///
     /// * `filename` - Path to the file to read
     pub fn from_snp_file(filename: &Path) -> Store {
+        Store::from_snp_file_mapped(filename).0
+    }
+
+    /// Same as `from_snp_file`, also returning on which line each address was defined
+    pub fn from_snp_file_mapped(filename: &Path) -> (Store, SourceMap) {
         // Todo return a Result for a richer explaination on the possible issues
         let file = File::open(filename);
         let file = match file {
@@ -185,9 +266,10 @@ impl Store {
             size: SSEM_STORE_WORDS,
         };
 
+        let mut source_map = SourceMap::default();
         let mut last_index: i32 = 0;
 
-        for (_, line) in reader.lines().enumerate() {
+        for (line_number, line) in reader.lines().enumerate() {
             let line = line.unwrap_or_else(|e| {
                 panic!(
                     "Error while reading '{}': {}",
@@ -198,9 +280,9 @@ impl Store {
 
             // Ignoring comments
             let exploded_line: Vec<&str> = line.splitn(2, ASM_COMMENT_CHAR).collect();
-            if exploded_line.len() > 0 {
-                let instruction = exploded_line[0].clone().trim();
-                if instruction.len() == 0 {
+            if !exploded_line.is_empty() {
+                let instruction = exploded_line[0].trim();
+                if instruction.is_empty() {
                     continue;
                 }
                 // Extracting tokens "<index>: <binary_word>"
@@ -229,6 +311,8 @@ impl Store {
                     );
                 }
 
+                source_map.insert(index, line_number + 1);
+
                 // Reverse the bit order: SSEM is least significant bit first
                 let word = i[1].trim().chars().rev().collect::<String>();
                 if word.len() != 32 {
@@ -254,13 +338,94 @@ impl Store {
 
         store._check();
 
-        store
+        (store, source_map)
+    }
+
+    /// Initializes the store with the given file in teleprinter code
+    ///
+    /// A tp file has the following form:
+    /// ```
+    /// 00: ///////
+    /// 01: £/T//// ; LDN 31
+    /// 02: //@////
+    /// ...
+    /// ```
+    /// Each numbered line represents a raw word in base 32, written with the characters of the Manchester teleprinter
+    /// code least significant first. Trailing `/` (zeros) can be left out. Erverything after ';' is ignored.
+    pub fn from_tp_file_mapped(filename: &Path) -> (Store, SourceMap) {
+        let source = std::fs::read_to_string(filename).unwrap_or_else(|e| {
+            panic!(
+                "Error while reading '{}': {}",
+                filename.to_str().unwrap_or(""),
+                e
+            );
+        });
+
+        let mut store = Store {
+            words: vec![0_i32; usize::try_from(SSEM_STORE_WORDS).unwrap()],
+            size: SSEM_STORE_WORDS,
+        };
+
+        let mut source_map = SourceMap::default();
+        let mut last_index: i32 = 0;
+
+        for (line_number, line) in source.lines().enumerate() {
+            // The comment character is not part of the teleprinter code
+            let instruction = line.split(ASM_COMMENT_CHAR).next().unwrap_or("").trim();
+            if instruction.is_empty() {
+                continue;
+            }
+            let Some((index, word)) = instruction.split_once(':') else {
+                panic!("Error near line '{}': invalid syntax", instruction);
+            };
+
+            let index: i32 = index.trim().parse().expect("Unable to read the number");
+            if index > 0 && index != last_index + 1 {
+                panic!(
+                    "Error near line '{}': expected index '{}'",
+                    instruction,
+                    last_index + 1
+                )
+            }
+            last_index = index;
+
+            if index >= store.size {
+                panic!(
+                    "Error near line '{}': Index '{}' is bigger than the machine size ({})",
+                    instruction, index, store.size,
+                );
+            }
+
+            source_map.insert(index, line_number + 1);
+            store.words[usize::try_from(index).unwrap()] = teleprinter::parse_word(word.trim())
+                .unwrap_or_else(|e| panic!("Error near line '{}': {}", instruction, e));
+        }
+
+        store._check();
+
+        (store, source_map)
+    }
+
+    /// Write the words in the .snp format, which `from_snp_file` reads back
+    pub fn write_snp(&self, output: &mut impl Write) -> io::Result<()> {
+        for (address, word) in self.words.iter().enumerate() {
+            writeln!(output, "{address:02}: {:032b}", word.reverse_bits())?;
+        }
+        Ok(())
+    }
+
+    /// Bits of a word in the order the machine shows them, least significant first
+    pub fn bits(word: i32) -> impl Iterator<Item = bool> {
+        (0..32).map(move |bit| word & (1 << bit) != 0)
     }
 
     /// Extract the opcode and data from the word at the given address
     pub fn decode_instruction(&self, address: i32) -> Result<(Opcode, i32), String> {
-        let word = self[address];
+        Store::decode_word(self[address])
+    }
 
+    /// Extract the opcode and data from the given word
+    pub fn decode_word(word: i32) -> Result<(Opcode, i32), String> {
         // Objective: extract opcode and data from word
         // word: 0b00000000000000000100000000011000
         //                         ===        =====
@@ -345,7 +510,7 @@ impl From<Vec<String>> for Store {
         }
 
         let store = Store {
-            words: words,
+            words,
             size: SSEM_STORE_WORDS,
         };
         store._check();
@@ -362,7 +527,10 @@ impl PartialEq for Store {
 impl fmt::Display for Store {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for word in self.words.iter() {
-            writeln!(f, " {:032b}", word.reverse_bits()).ok();
+            let line: String = Store::bits(*word)
+                .map(|bit| if bit { '1' } else { '0' })
+                .collect();
+            writeln!(f, " {line}").ok();
         }
         Ok(())
     }
@@ -370,7 +538,10 @@ impl fmt::Display for Store {
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+
     use crate::ssem::opcode::Opcode;
+    use crate::ssem::testing::TempDir;
 
     use super::Store;
 
@@ -585,4 +756,16 @@ mod tests {
         ]);
         assert!(s1 != s2);
     }
+
+    #[test]
+    fn teleprinter_code() {
+        let asm = Store::from_file_mapped(Path::new("samples/ssem/fibonacci.asm")).0;
+        let tp = Store::from_file_mapped(Path::new("samples/ssem/fibonacci.tp")).0;
+        assert_eq!(asm, tp);
+
+        let dir = TempDir::new();
+        let store =
+            Store::from_asm_file(&dir.write("literal.asm", "00 NUM #CE\n01 LDN #B\n02 NUM -1\n"));
+        assert_eq!(store.words[..3], [14 + 32, 0b010 << 13 | 25, -1]);
+    }
 }
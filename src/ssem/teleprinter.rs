@@ -0,0 +1,80 @@
+//! Manchester teleprinter code: 5-bit groups written as single characters
+//!
+//! Each character stands for a group of 5 bits written least significant first, e.g. `E` is `10000`, which is 1.
+
+/// Characters of the codes 0 to 31
+pub const ALPHABET: [char; 32] = [
+    '/', 'E', '@', 'A', ':', 'S', 'I', 'U', '½', 'D', 'R', 'J', 'N', 'F', 'C', 'K', 'T', 'Z', 'L',
+    'W', 'H', 'Y', 'P', 'Q', 'O', 'B', 'G', '"', 'M', 'X', 'V', '£',
+];
+
+/// Character of the 5 least significant bits of the given value
+pub fn character(value: i32) -> char {
+    ALPHABET[(value & 0b11111) as usize]
+}
+
+/// Code of the given character, ignoring case
+pub fn code(character: char) -> Option<i32> {
+    let character = character.to_ascii_uppercase();
+    ALPHABET
+        .iter()
+        .position(|c| *c == character)
+        .map(|code| code as i32)
+}
+
+/// Number of characters needed for a 32-bit word, the last one only carrying 2 bits
+pub const WORD_CHARACTERS: usize = 7;
+
+/// Writes a word as characters of 5 bits, least significant first as on the machine, e.g. 1 is `E//////`
+pub fn format_word(word: i32) -> String {
+    (0..WORD_CHARACTERS)
+        .map(|i| character(((word as u32) >> (5 * i)) as i32))
+        .collect()
+}
+
+/// Reads a word written as by `format_word`. Missing characters at the end are zeros, e.g. 1 is also `E`.
+pub fn parse_word(input: &str) -> Result<i32, String> {
+    let mut word = 0u32;
+    for (i, c) in input.chars().enumerate() {
+        let value = code(c).ok_or_else(|| {
+            format!("invalid word '{input}': '{c}' is not a teleprinter character")
+        })? as u32;
+        if i >= WORD_CHARACTERS || (i == WORD_CHARACTERS - 1 && value > 0b11) {
+            return Err(format!("invalid word '{input}': it doesn't fit in 32 bits"));
+        }
+        word |= value << (5 * i);
+    }
+    Ok(word as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{character, code, format_word, parse_word, ALPHABET};
+
+    #[test]
+    fn round_trip() {
+        for (value, c) in ALPHABET.iter().enumerate() {
+            assert_eq!(code(*c), Some(value as i32));
+            assert_eq!(character(value as i32), *c);
+        }
+        assert_eq!(code('e'), Some(1));
+        assert_eq!(code('1'), None);
+        assert_eq!(character(32 + 20), 'H');
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(format_word(1), "E//////");
+        assert_eq!(format_word(-1), "££££££A");
+        assert_eq!(parse_word("E"), Ok(1));
+        assert_eq!(parse_word("££££££A"), Ok(-1));
+        // LDN 20 is opcode 2 (bits 13 to 15) and operand 20
+        assert_eq!(parse_word("H/T"), Ok(0b010 << 13 | 20));
+        for word in [0, 46, -123_456_789, i32::MIN, i32::MAX] {
+            assert_eq!(parse_word(&format_word(word)), Ok(word));
+        }
+        assert!(parse_word("££££££E£").is_err());
+        assert!(parse_word("££££££S").is_err());
+        assert!(parse_word("E1").is_err());
+    }
+}
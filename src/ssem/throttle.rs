@@ -0,0 +1,139 @@
+//! Real-time execution, paced against the wall clock
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::simulator::{Simulator, StopReason};
+
+/// Duration of the slices of execution between two pauses
+const SLICE: Duration = Duration::from_millis(10);
+
+/// How well the requested speed was held during a paced run
+#[derive(Debug, Copy, Clone)]
+pub struct PacingReport {
+    pub cycles: u64,
+    pub elapsed: Duration,
+    pub target_ips: f64,
+
+    /// Longest time the execution was behind schedule
+    pub max_lag: Duration,
+}
+
+impl PacingReport {
+    pub fn achieved_ips(&self) -> f64 {
+        self.cycles as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Relative difference between the achieved and requested speeds
+    pub fn error(&self) -> f64 {
+        (self.achieved_ips() - self.target_ips) / self.target_ips
+    }
+}
+
+impl fmt::Display for PacingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Paced at {:.1} ips for a target of {:.1} ips ({:+.3}%), at most {:.2?} behind schedule",
+            self.achieved_ips(),
+            self.target_ips,
+            self.error() * 100.0,
+            self.max_lag,
+        )
+    }
+}
+
+/// Keeps a run on schedule, sleeping between slices of execution.
+///
+/// The schedule is computed from the start of the run rather than from the previous slice, so that sleeping
+/// inaccuracies don't accumulate.
+#[derive(Debug)]
+pub struct Pacer {
+    ips: f64,
+    start: Instant,
+    max_lag: Duration,
+}
+
+impl Pacer {
+    pub fn new(ips: f64) -> Pacer {
+        assert!(ips > 0.0, "The speed must be positive");
+        Pacer {
+            ips,
+            start: Instant::now(),
+            max_lag: Duration::ZERO,
+        }
+    }
+
+    /// Number of cycles to execute between two pauses
+    pub fn slice_cycles(&self) -> u32 {
+        (self.ips * SLICE.as_secs_f64()).max(1.0) as u32
+    }
+
+    /// Sleeps until the given amount of cycles is due since the start
+    pub fn wait(&mut self, cycles: u32) {
+        let schedule = self.start + Duration::from_secs_f64(f64::from(cycles) / self.ips);
+        let now = Instant::now();
+        if schedule > now {
+            thread::sleep(schedule - now);
+        } else {
+            self.max_lag = self.max_lag.max(now - schedule);
+        }
+    }
+
+    /// Shifts the schedule after the run was suspended for the given duration
+    pub fn suspended(&mut self, duration: Duration) {
+        self.start += duration;
+    }
+
+    pub fn report(&self, cycles: u32) -> PacingReport {
+        PacingReport {
+            cycles: u64::from(cycles),
+            elapsed: self.start.elapsed(),
+            target_ips: self.ips,
+            max_lag: self.max_lag,
+        }
+    }
+}
+
+/// Same as `Simulator::run`, executing the given amount of instructions per second.
+pub fn run_paced(simulator: &mut Simulator, max_cycles: u32, ips: f64) -> PacingReport {
+    let mut pacer = Pacer::new(ips);
+    let slice_cycles = pacer.slice_cycles();
+    let mut cycles = 0u32;
+
+    while cycles < max_cycles {
+        cycles += simulator.run(slice_cycles.min(max_cycles - cycles));
+        if simulator.stop_reason() != Some(StopReason::MaxCycles) {
+            break;
+        }
+        pacer.wait(cycles);
+    }
+
+    pacer.report(cycles)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crate::ssem::simulator::Simulator;
+
+    use super::run_paced;
+
+    #[test]
+    fn paced() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/nightmare.snp"));
+        let report = run_paced(&mut simulator, 500, 10_000.0);
+        assert_eq!(report.cycles, 500);
+        assert!(report.elapsed >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn stops() {
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let report = run_paced(&mut simulator, 100_000, 1_000_000.0);
+        assert!(report.cycles < 1000);
+    }
+}
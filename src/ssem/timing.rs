@@ -0,0 +1,226 @@
+//! Timing model of the real machine, to know how long a run would have taken on the Baby
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Speed of the machine, expressed the way it was built: instructions are made of beats
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimingModel {
+    pub beats_per_instruction: u32,
+    pub beat_duration: Duration,
+}
+
+impl TimingModel {
+    /// The 1948 machine, fitted to Kilburn's highest factor run of 21 June 1948: about 3.5 million instructions in
+    /// 52 minutes, i.e. four beats of 0.223 ms and 0.89 ms per instruction
+    pub const ORIGINAL: TimingModel = TimingModel {
+        beats_per_instruction: 4,
+        beat_duration: Duration::from_nanos(222_857),
+    };
+
+    /// The 1998 replica, running at about 700 instructions per second
+    pub const REPLICA: TimingModel = TimingModel {
+        beats_per_instruction: 4,
+        beat_duration: Duration::from_nanos(357_143),
+    };
+
+    pub fn instruction_duration(&self) -> Duration {
+        self.beat_duration * self.beats_per_instruction
+    }
+
+    pub fn instructions_per_second(&self) -> f64 {
+        1.0 / self.instruction_duration().as_secs_f64()
+    }
+
+    /// Time the machine takes to execute the given amount of instructions
+    pub fn duration(&self, cycles: u64) -> Duration {
+        Duration::from_secs_f64(self.instruction_duration().as_secs_f64() * cycles as f64)
+    }
+}
+
+impl Default for TimingModel {
+    fn default() -> Self {
+        TimingModel::ORIGINAL
+    }
+}
+
+impl FromStr for TimingModel {
+    type Err = String;
+
+    /// Parses a preset name (`1948` or `1998`) or a speed in instructions per second, e.g. `700ips`
+    fn from_str(input: &str) -> Result<TimingModel, Self::Err> {
+        match input {
+            "1948" | "original" => Ok(TimingModel::ORIGINAL),
+            "1998" | "replica" => Ok(TimingModel::REPLICA),
+            _ => {
+                let ips = input
+                    .strip_suffix("ips")
+                    .and_then(|ips| ips.trim().parse::<f64>().ok())
+                    .filter(|ips| *ips > 0.0)
+                    .ok_or(format!(
+                        "unknown timing model '{input}', expected 1948, 1998 or a speed such as 700ips"
+                    ))?;
+                let beats_per_instruction = TimingModel::ORIGINAL.beats_per_instruction;
+                Ok(TimingModel {
+                    beats_per_instruction,
+                    beat_duration: Duration::from_secs_f64(
+                        1.0 / ips / beats_per_instruction as f64,
+                    ),
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for TimingModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} beats of {:.3} ms, {:.3} ms per instruction, {:.0} ips",
+            self.beats_per_instruction,
+            self.beat_duration.as_secs_f64() * 1000.0,
+            self.instruction_duration().as_secs_f64() * 1000.0,
+            self.instructions_per_second(),
+        )
+    }
+}
+
+/// Displays a duration in hours, minutes and seconds
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HumanDuration(pub Duration);
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    /// Parses amounts of hours, minutes, seconds and milliseconds such as `1h 30m`, `2.5s` or `500ms`. A bare number
+    /// is a number of seconds.
+    fn from_str(input: &str) -> Result<HumanDuration, Self::Err> {
+        let invalid = || format!("invalid duration '{input}', expected e.g. 90s, 1m 30s or 500ms");
+        let mut seconds = 0.0;
+        let mut rest = input.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        while !rest.is_empty() {
+            let number_end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let unit_end = rest[number_end..]
+                .find(|c: char| c.is_ascii_digit() || c.is_whitespace())
+                .map_or(rest.len(), |end| number_end + end);
+            let number: f64 = rest[..number_end].parse().map_err(|_| invalid())?;
+            seconds += number
+                * match &rest[number_end..unit_end] {
+                    "h" => 3600.0,
+                    "m" | "min" => 60.0,
+                    "s" | "" => 1.0,
+                    "ms" => 0.001,
+                    _ => return Err(invalid()),
+                };
+            rest = rest[unit_end..].trim_start();
+        }
+        Duration::try_from_secs_f64(seconds)
+            .map(HumanDuration)
+            .map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.0.as_secs_f64();
+        // Rounded before splitting, so that 59.96 s is 1m 00.0s rather than 60.0s
+        let millis = (seconds * 1000.0).round() as u64;
+        if millis < 60_000 {
+            return write!(f, "{}.{:03}s", millis / 1000, millis % 1000);
+        }
+        let tenths = (seconds * 10.0).round() as u64;
+        let (hours, minutes) = (tenths / 36_000, tenths / 600 % 60);
+        let seconds = tenths % 600;
+        if hours > 0 {
+            write!(
+                f,
+                "{hours}h {minutes:02}m {:02}.{}s",
+                seconds / 10,
+                seconds % 10
+            )
+        } else {
+            write!(f, "{minutes}m {:02}.{}s", seconds / 10, seconds % 10)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use super::{HumanDuration, TimingModel};
+
+    #[test]
+    fn presets() {
+        assert_eq!(
+            TimingModel::ORIGINAL.instruction_duration(),
+            Duration::from_nanos(891_428)
+        );
+        assert_eq!(
+            TimingModel::REPLICA.instructions_per_second().round(),
+            700.0
+        );
+        assert_eq!(TimingModel::from_str("1998"), Ok(TimingModel::REPLICA));
+    }
+
+    #[test]
+    fn from_ips() {
+        let model = TimingModel::from_str("1000ips").unwrap();
+        assert_eq!(model.beats_per_instruction, 4);
+        assert_eq!(model.instruction_duration(), Duration::from_millis(1));
+        assert!(TimingModel::from_str("0ips").is_err());
+        assert!(TimingModel::from_str("fast").is_err());
+    }
+
+    #[test]
+    fn duration() {
+        // Kilburn's highest factor run
+        let duration = TimingModel::ORIGINAL.duration(3_500_000);
+        assert_eq!(duration.as_secs_f64().round(), (52 * 60) as f64);
+        assert_eq!(HumanDuration(duration).to_string(), "52m 00.0s");
+        assert_eq!(
+            HumanDuration(TimingModel::ORIGINAL.duration(7_000_000)).to_string(),
+            "1h 44m 00.0s"
+        );
+        assert_eq!(
+            HumanDuration(Duration::from_millis(2500)).to_string(),
+            "2.500s"
+        );
+        assert_eq!(
+            HumanDuration(Duration::from_secs(61)).to_string(),
+            "1m 01.0s"
+        );
+        // Rounding carries into the minutes and hours
+        assert_eq!(
+            HumanDuration(Duration::from_millis(119_960)).to_string(),
+            "2m 00.0s"
+        );
+        assert_eq!(
+            HumanDuration(Duration::from_millis(3_599_970)).to_string(),
+            "1h 00m 00.0s"
+        );
+        assert_eq!(
+            HumanDuration(Duration::from_micros(59_999_900)).to_string(),
+            "1m 00.0s"
+        );
+    }
+
+    #[test]
+    fn parse_duration() {
+        let parse = |input| HumanDuration::from_str(input).map(|d| d.0);
+        assert_eq!(parse("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse("1h 30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse("1m30s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse("2.5s"), Ok(Duration::from_millis(2500)));
+        assert_eq!(parse("500ms"), Ok(Duration::from_millis(500)));
+        assert!(parse("").is_err());
+        assert!(parse("5 days").is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Per-cycle execution trace
+
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use super::opcode::Opcode;
+use super::simulator::{ExecutedInstruction, Observer};
+use super::store::Store;
+
+/// How trace records are written
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceFormat {
+    /// Aligned columns meant to be read by humans
+    Text,
+
+    /// Comma separated values, with a header line
+    Csv,
+
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<TraceFormat, Self::Err> {
+        match input {
+            "text" => Ok(TraceFormat::Text),
+            "csv" => Ok(TraceFormat::Csv),
+            "json" | "jsonl" => Ok(TraceFormat::Json),
+            _ => Err(format!(
+                "unknown trace format '{input}', expected text, csv or json"
+            )),
+        }
+    }
+}
+
+/// Inclusive range written `START-END`, or a single value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span<T>(pub RangeInclusive<T>);
+
+impl<T: FromStr + Copy> FromStr for Span<T> {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Span<T>, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<T>()
+                .map_err(|_| format!("invalid number '{value}'"))
+        };
+        match input.split_once('-') {
+            Some((start, end)) => Ok(Span(parse(start)?..=parse(end)?)),
+            None => {
+                let value = parse(input)?;
+                Ok(Span(value..=value))
+            }
+        }
+    }
+}
+
+/// Set of opcodes written as a comma separated list of mnemonics
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodeSet(pub Vec<Opcode>);
+
+impl FromStr for OpcodeSet {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<OpcodeSet, Self::Err> {
+        input
+            .split(',')
+            .map(|mnemonic| {
+                let mnemonic = mnemonic.trim().to_ascii_uppercase();
+                Opcode::from_str(&mnemonic).map_err(|_| format!("unknown opcode '{mnemonic}'"))
+            })
+            .collect::<Result<Vec<Opcode>, String>>()
+            .map(OpcodeSet)
+    }
+}
+
+/// Selects which instructions get traced. Every instruction is traced by default.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub addresses: Option<RangeInclusive<i32>>,
+    pub opcodes: Option<Vec<Opcode>>,
+    pub cycles: Option<RangeInclusive<u64>>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, instruction: &ExecutedInstruction) -> bool {
+        self.cycles
+            .as_ref()
+            .is_none_or(|c| c.contains(&instruction.cycle))
+            && self
+                .addresses
+                .as_ref()
+                .is_none_or(|a| a.contains(&instruction.address))
+            && self
+                .opcodes
+                .as_ref()
+                .is_none_or(|o| o.contains(&instruction.opcode))
+    }
+}
+
+/// Writes a record for each executed instruction
+pub struct TraceWriter<W: Write> {
+    output: W,
+    format: TraceFormat,
+    filter: TraceFilter,
+    header_written: bool,
+
+    /// First error encountered, writing stops afterwards
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(output: W, format: TraceFormat, filter: TraceFilter) -> TraceWriter<W> {
+        TraceWriter {
+            output,
+            format,
+            filter,
+            header_written: false,
+            error: None,
+        }
+    }
+
+    fn write(&mut self, instruction: &ExecutedInstruction) -> io::Result<()> {
+        let i = instruction;
+        match self.format {
+            TraceFormat::Text => writeln!(
+                self.output,
+                "{:>10} CI={:02} {:032b} {:<3} {:2} {:>11} A={:>11} -> {:>11}",
+                i.cycle,
+                i.address,
+                i.word.reverse_bits(),
+                i.opcode,
+                i.operand,
+                Optional(i.operand_value, ""),
+                i.a_before,
+                i.a_after,
+            ),
+            TraceFormat::Csv => {
+                if !self.header_written {
+                    writeln!(
+                        self.output,
+                        "cycle,ci,word,opcode,operand,operand_value,a_before,a_after"
+                    )?;
+                    self.header_written = true;
+                }
+                writeln!(
+                    self.output,
+                    "{},{},{},{},{},{},{},{}",
+                    i.cycle,
+                    i.address,
+                    i.word,
+                    i.opcode,
+                    i.operand,
+                    Optional(i.operand_value, ""),
+                    i.a_before,
+                    i.a_after,
+                )
+            }
+            TraceFormat::Json => writeln!(
+                self.output,
+                "{{\"cycle\":{},\"ci\":{},\"word\":{},\"opcode\":\"{}\",\"operand\":{},\"operand_value\":{},\"a_before\":{},\"a_after\":{}}}",
+                i.cycle,
+                i.address,
+                i.word,
+                i.opcode,
+                i.operand,
+                Optional(i.operand_value, "null"),
+                i.a_before,
+                i.a_after,
+            ),
+        }
+    }
+}
+
+impl<W: Write> Observer for TraceWriter<W> {
+    fn instruction_executed(&mut self, instruction: &ExecutedInstruction, _: &Store) {
+        if self.error.is_none() && self.filter.matches(instruction) {
+            if let Err(e) = self.write(instruction) {
+                eprintln!("Unable to write the trace: {e}");
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl<W: Write> Drop for TraceWriter<W> {
+    fn drop(&mut self) {
+        if self.error.is_none() {
+            if let Err(e) = self.output.flush() {
+                eprintln!("Unable to write the trace: {e}");
+            }
+        }
+    }
+}
+
+/// Displays an optional value, or the given placeholder
+struct Optional<'a>(Option<i32>, &'a str);
+
+impl fmt::Display for Optional<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(value) => value.fmt(f),
+            None => self.1.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use crate::ssem::opcode::Opcode;
+    use crate::ssem::simulator::Simulator;
+    use crate::ssem::testing::SharedOutput;
+
+    use super::{OpcodeSet, Span, TraceFilter, TraceFormat, TraceWriter};
+
+    fn trace(format: TraceFormat, filter: TraceFilter, cycles: u32) -> String {
+        let output = SharedOutput::default();
+        let mut simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        simulator.add_observer(Box::new(TraceWriter::new(output.clone(), format, filter)));
+        simulator.run(cycles);
+        drop(simulator);
+        output.text()
+    }
+
+    #[test]
+    fn formats() {
+        let csv = trace(TraceFormat::Csv, TraceFilter::default(), 2);
+        assert_eq!(
+            csv,
+            "cycle,ci,word,opcode,operand,operand_value,a_before,a_after\n\
+             0,1,16415,LDN,31,0,0,0\n\
+             1,2,32768,SUB,0,1,0,-1\n"
+        );
+
+        let json = trace(TraceFormat::Json, TraceFilter::default(), 3);
+        assert_eq!(
+            json.lines().nth(2).unwrap(),
+            "{\"cycle\":2,\"ci\":3,\"word\":24607,\"opcode\":\"STO\",\"operand\":31,\"operand_value\":null,\"a_before\":-1,\"a_after\":-1}"
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let filter = TraceFilter {
+            addresses: Some(Span::<i32>::from_str("9-17").unwrap().0),
+            opcodes: Some(OpcodeSet::from_str("ldn,sub").unwrap().0),
+            cycles: Some(Span::<u64>::from_str("0-20").unwrap().0),
+        };
+        let text = trace(TraceFormat::Text, filter, 100);
+        let addresses: Vec<&str> = text.lines().map(|l| &l[11..16]).collect();
+        assert_eq!(addresses, ["CI=09", "CI=10", "CI=12", "CI=14", "CI=16"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(OpcodeSet::from_str("LDN,FOO").is_err());
+        assert_eq!(OpcodeSet::from_str("stp").unwrap().0, [Opcode::STP]);
+        assert!(Span::<i32>::from_str("3-x").is_err());
+        assert!(TraceFormat::from_str("xml").is_err());
+    }
+}
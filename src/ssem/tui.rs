@@ -0,0 +1,478 @@
+//! Full-screen front panel in the terminal: the tubes update live while the machine runs, and the keys of the panel
+//! are operated from the keyboard
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use super::crt::{Glyphs, TubeRenderer};
+use super::display;
+use super::panel::{FrontPanel, Key, Mode};
+use super::teleprinter;
+
+/// Time between two refreshes of the screen
+const FRAME: Duration = Duration::from_millis(33);
+
+/// Keyboard shortcuts, as shown on screen
+const HELP: [&str; 9] = [
+    "r      Stop/Run         s  single shot",
+    "c      KC  (control)    a  KAC (accumulator)",
+    "k      KLC (line)       x  KSC (store)",
+    "↑ ↓    line switches    ← →  typewriter key",
+    "space  toggle the bit of the typewriter key",
+    "w      write a word on the line (number or #teleprinter)",
+    "m      Auto/Manual      f  function switches",
+    "+ -    speed ×2 ÷2      0  full speed",
+    "q      quit",
+];
+
+const GREEN: &str = "\x1b[92;40m";
+const REVERSE: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+/// State of the terminal front panel, apart from the terminal itself
+pub struct Tui {
+    pub panel: FrontPanel,
+
+    /// Speed while running, as fast as possible if `None`
+    pub ips: Option<f64>,
+
+    /// How the spots of the tubes are drawn
+    pub glyphs: Glyphs,
+
+    /// Draw the tubes in phosphor green
+    pub green: bool,
+
+    /// Bit selected for the typewriter key, the line being given by the line switches
+    typewriter_bit: u32,
+
+    /// Word being typed for the selected line, while in entry mode
+    entry: Option<String>,
+
+    /// Last message shown under the tubes
+    message: String,
+
+    /// Cycles owed to the target speed, carried from a frame to the next
+    owed_cycles: f64,
+
+    /// Speed measured over the last frames
+    measured_ips: f64,
+}
+
+impl Tui {
+    pub fn new(panel: FrontPanel, ips: Option<f64>, glyphs: Glyphs, green: bool) -> Tui {
+        Tui {
+            panel,
+            ips,
+            glyphs,
+            green,
+            typewriter_bit: 0,
+            entry: None,
+            message: String::new(),
+            owed_cycles: 0.0,
+            measured_ips: 0.0,
+        }
+    }
+
+    /// Handles a key press. Returns `false` once the user asked to quit.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.kind == KeyEventKind::Release {
+            return true;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        if let Some(entry) = &mut self.entry {
+            match key.code {
+                KeyCode::Char(c) => entry.push(c),
+                KeyCode::Backspace => {
+                    entry.pop();
+                }
+                KeyCode::Enter => self.write_entry(),
+                KeyCode::Esc => self.entry = None,
+                _ => (),
+            }
+            return true;
+        }
+
+        let size = self.panel.simulator.store.size;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('r') => {
+                // The machine runs over the following frames
+                let running = !self.panel.running();
+                self.panel.set_run_switch(running);
+                self.message = if self.panel.stop_light() && running {
+                    "The Stop light is lit, press KC first".to_string()
+                } else {
+                    String::new()
+                };
+            }
+            KeyCode::Char('s') => self.panel.press(Key::SingleShot),
+            KeyCode::Char('c') => self.panel.press(Key::KC),
+            KeyCode::Char('a') => self.panel.press(Key::KAC),
+            KeyCode::Char('k') => self.panel.press(Key::KLC),
+            KeyCode::Char('x') => self.panel.press(Key::KSC),
+            KeyCode::Char(' ') => self.panel.press(Key::Typewriter(self.typewriter_bit)),
+            KeyCode::Up => {
+                self.panel.line_switches = (self.panel.line_switches - 1).rem_euclid(size);
+            }
+            KeyCode::Down => {
+                self.panel.line_switches = (self.panel.line_switches + 1).rem_euclid(size);
+            }
+            KeyCode::Left => self.typewriter_bit = (self.typewriter_bit + 31) % 32,
+            KeyCode::Right => self.typewriter_bit = (self.typewriter_bit + 1) % 32,
+            KeyCode::Char('w') => self.entry = Some(String::new()),
+            KeyCode::Char('m') => {
+                self.panel.mode = match self.panel.mode {
+                    Mode::Auto => Mode::Manual,
+                    Mode::Manual => Mode::Auto,
+                }
+            }
+            KeyCode::Char('f') => {
+                self.panel.function_switches = (self.panel.function_switches + 1) % 8
+            }
+            KeyCode::Char('+') => self.ips = self.ips.map(|ips| ips * 2.0),
+            KeyCode::Char('-') => self.ips = Some(self.ips.map_or(1_000_000.0, |ips| ips / 2.0)),
+            KeyCode::Char('0') => self.ips = None,
+            _ => (),
+        }
+        true
+    }
+
+    /// Writes the typed word to the line selected by the line switches
+    fn write_entry(&mut self) {
+        let Some(entry) = self.entry.take() else {
+            return;
+        };
+        let entry = entry.trim();
+        let word = match entry.strip_prefix('#') {
+            Some(characters) => teleprinter::parse_word(characters),
+            None => entry
+                .parse::<i32>()
+                .map_err(|_| format!("invalid number '{entry}'")),
+        };
+        let line = self.panel.line_switches;
+        match word {
+            Ok(word) if self.panel.write => {
+                self.panel.simulator.write_word(line, word);
+                self.message = format!("line {line} <- {word}");
+            }
+            Ok(_) => self.message = "The Read/Write switch is on Read".to_string(),
+            Err(message) => self.message = message,
+        }
+    }
+
+    /// Runs the machine for a frame of the given duration
+    pub fn run_frame(&mut self, duration: Duration) {
+        if !self.panel.running() || self.panel.stop_light() {
+            self.owed_cycles = 0.0;
+            self.measured_ips = 0.0;
+            return;
+        }
+
+        let start = Instant::now();
+        let cycles = match self.ips {
+            Some(ips) => {
+                self.owed_cycles += ips * duration.as_secs_f64();
+                let cycles = self.panel.run_for(self.owed_cycles as u32);
+                self.owed_cycles = (self.owed_cycles - f64::from(cycles)).min(ips);
+                cycles
+            }
+            None => {
+                // Leaves some of the frame to draw the screen
+                let mut cycles = 0;
+                while start.elapsed() < duration / 2 && self.panel.running() {
+                    let ran = self.panel.run_for(100_000);
+                    cycles += ran;
+                    if ran == 0 {
+                        break;
+                    }
+                }
+                cycles
+            }
+        };
+        self.measured_ips =
+            self.measured_ips * 0.8 + 0.2 * f64::from(cycles) / duration.as_secs_f64();
+    }
+
+    /// Lines of the screen, with ANSI escape sequences
+    pub fn screen(&self) -> Vec<String> {
+        let simulator = &self.panel.simulator;
+        let renderer = TubeRenderer::new(self.glyphs, false);
+        // Each character of text holds several spots with blocks and braille
+        let (lines_per_row, bits_per_char) = match self.glyphs {
+            Glyphs::Dots => (1, 1),
+            Glyphs::Blocks => (2, 1),
+            Glyphs::Braille => (4, 2),
+        };
+        let width = 32 / bits_per_char;
+        let theme = |line: String| {
+            if self.green {
+                format!("{GREEN}{line}{RESET}")
+            } else {
+                line
+            }
+        };
+        let selected = self.panel.line_switches;
+
+        let mut store: Vec<String> = renderer
+            .lines(&simulator.store.words)
+            .into_iter()
+            .enumerate()
+            .map(|(row, spots)| {
+                let line = row * lines_per_row;
+                let is_selected = (line..line + lines_per_row).contains(&(selected as usize));
+                let mark = if is_selected { '▶' } else { ' ' };
+                let spots = if is_selected {
+                    // Highlights the character holding the spot of the typewriter key
+                    let chars: Vec<char> = spots.chars().collect();
+                    let column = self.typewriter_bit as usize / bits_per_char;
+                    let before: String = chars[..column].iter().collect();
+                    let after: String = chars[column + 1..].iter().collect();
+                    theme(before) + REVERSE + &chars[column].to_string() + RESET + &theme(after)
+                } else {
+                    theme(spots)
+                };
+                format!("{mark}{line:02} {spots}")
+            })
+            .collect();
+        let accumulator = theme(renderer.lines(&[simulator.a]).remove(0));
+        let present = simulator.store[simulator.ci.rem_euclid(simulator.store.size)];
+        let control = renderer.lines(&[simulator.ci, present]);
+
+        let state = if self.panel.stop_light() {
+            "STOP LIGHT"
+        } else if self.panel.running() {
+            "RUN"
+        } else {
+            "STOP"
+        };
+        let speed = match self.ips {
+            Some(ips) => format!("{ips:.0} ips"),
+            None => "full speed".to_string(),
+        };
+        let mut info = vec![
+            format!("Manchester Baby        {state}"),
+            String::new(),
+            format!(
+                "CI  {:>11}   next {}",
+                simulator.ci,
+                simulator.next_address()
+            ),
+            format!("PI  {}", display::instruction(present)),
+            format!("A   {:>11}", simulator.a),
+            format!("Cycles {}", simulator.cycles),
+            format!("Speed  {:.0} ips (target {speed})", self.measured_ips),
+            String::new(),
+            format!(
+                "Mode {:?}   L {:02}   F {}   {}",
+                self.panel.mode,
+                self.panel.line_switches,
+                self.panel.function_switches,
+                if self.panel.write { "Write" } else { "Read" }
+            ),
+            format!(
+                "Line {:02} = {}   typewriter bit {}",
+                selected, simulator.store[selected], self.typewriter_bit
+            ),
+            String::new(),
+        ];
+        info.extend(HELP.iter().map(|line| line.to_string()));
+        info.push(String::new());
+        info.push(match &self.entry {
+            Some(entry) => format!("Line {selected:02} = {entry}_"),
+            None => self.message.clone(),
+        });
+
+        // The store tube on the left, everything else on the right
+        let mut screen = Vec::new();
+        screen.push(format!("    {:─^width$}", "Store"));
+        let right = info.len().max(store.len());
+        store.resize(right, String::new());
+        for (line, info) in store
+            .iter()
+            .zip(info.iter().chain(std::iter::repeat(&String::new())))
+        {
+            let padding = if line.is_empty() { width + 4 } else { 0 };
+            screen.push(format!("{line}{:padding$}   {info}", ""));
+        }
+        screen.push(String::new());
+        screen.push(format!("    {:─^width$}", "Accumulator"));
+        screen.push(format!("    {accumulator}"));
+        screen.push(format!("    {:─^width$}", "Control"));
+        for line in control {
+            screen.push(format!("    {}", theme(line)));
+        }
+        screen
+    }
+}
+
+/// Restores the terminal when dropped, even on errors
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        execute!(io::stdout(), Show, LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+/// Operates the front panel from the terminal until the user quits. Returns the panel in its final state.
+pub fn run_tui(mut tui: Tui) -> io::Result<FrontPanel> {
+    let terminal = Terminal::enter()?;
+    let mut output = io::stdout().lock();
+
+    loop {
+        let frame_start = Instant::now();
+        tui.run_frame(FRAME);
+
+        queue!(output, MoveTo(0, 0))?;
+        for (row, line) in tui.screen().iter().enumerate() {
+            queue!(
+                output,
+                MoveTo(0, row as u16),
+                Clear(ClearType::UntilNewLine)
+            )?;
+            write!(output, "{line}")?;
+        }
+        queue!(output, Clear(ClearType::FromCursorDown))?;
+        output.flush()?;
+
+        // Waits for the rest of the frame, or for keys
+        let mut quit = false;
+        while !quit {
+            let remaining = FRAME.saturating_sub(frame_start.elapsed());
+            if !event::poll(remaining)? {
+                break;
+            }
+            if let Event::Key(key) = event::read()? {
+                quit = !tui.handle_key(key);
+            }
+        }
+        if quit {
+            break;
+        }
+    }
+
+    drop(terminal);
+    Ok(tui.panel)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use crate::ssem::crt::Glyphs;
+    use crate::ssem::panel::FrontPanel;
+    use crate::ssem::simulator::Simulator;
+
+    use super::{Tui, REVERSE};
+
+    fn press(tui: &mut Tui, code: KeyCode) -> bool {
+        tui.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn feed_the_pet() {
+        let simulator = Simulator::from_file(Path::new("samples/ssem/virpet.asm"));
+        let mut tui = Tui::new(
+            FrontPanel::new(simulator, 1_000_000),
+            Some(10_000.0),
+            Glyphs::Dots,
+            false,
+        );
+
+        // Selects line 22, then toggles its bit 8 with the typewriter
+        for _ in 0..9 {
+            press(&mut tui, KeyCode::Up);
+        }
+        for _ in 0..8 {
+            press(&mut tui, KeyCode::Right);
+        }
+        let food = tui.panel.simulator.store[22];
+        press(&mut tui, KeyCode::Char(' '));
+        assert_eq!(tui.panel.simulator.store[22], food ^ 256);
+        press(&mut tui, KeyCode::Char(' '));
+        assert_eq!(tui.panel.simulator.store[22], food);
+
+        // Manual entry of a whole word
+        press(&mut tui, KeyCode::Char('w'));
+        for c in "500".chars() {
+            press(&mut tui, KeyCode::Char(c));
+        }
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(tui.panel.simulator.store[22], 500);
+
+        press(&mut tui, KeyCode::Char('r'));
+        assert!(tui.panel.running());
+        tui.run_frame(Duration::from_millis(10));
+        assert_eq!(tui.panel.simulator.cycles, 100);
+        assert!(tui.screen().iter().any(|line| line.contains("Cycles 100")));
+        assert!(!press(&mut tui, KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn single_shot() {
+        let simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let mut tui = Tui::new(
+            FrontPanel::new(simulator, 1_000_000),
+            None,
+            Glyphs::Dots,
+            true,
+        );
+        press(&mut tui, KeyCode::Char('s'));
+        assert_eq!(tui.panel.simulator.cycles, 1);
+        press(&mut tui, KeyCode::Char('r'));
+        tui.run_frame(Duration::from_millis(10));
+        assert!(tui.panel.stop_light());
+        assert!(tui.screen()[1].contains("STOP LIGHT"));
+    }
+
+    #[test]
+    fn glyphs() {
+        let simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let mut tui = Tui::new(
+            FrontPanel::new(simulator, 1_000_000),
+            None,
+            Glyphs::Braille,
+            false,
+        );
+        tui.panel.line_switches = 5;
+        tui.typewriter_bit = 7;
+
+        // Lines 4 to 7 share a row of braille, two bits per character
+        let screen = tui.screen();
+        assert_eq!(screen[0], format!("    {:─^16}", "Store"));
+        let row: Vec<char> = screen[2].chars().collect();
+        let spots: String = row[4..7].iter().collect();
+        assert_eq!(
+            screen[2].split(REVERSE).next(),
+            Some(format!("▶04 {spots}").as_str())
+        );
+        assert!(!screen[3].starts_with('▶'));
+
+        tui.glyphs = Glyphs::Blocks;
+        let screen = tui.screen();
+        assert!(screen[3].starts_with("▶04 "));
+        assert_eq!(
+            screen[3].split(REVERSE).next().unwrap().chars().count(),
+            4 + 7
+        );
+    }
+}
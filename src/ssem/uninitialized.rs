@@ -0,0 +1,97 @@
+//! Detection of reads of store words that were never set, neither by the loaded file nor by STO
+
+use std::fmt;
+
+use super::opcode::Opcode;
+
+/// An instruction that read a word the program never set, possibly several times
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UninitializedRead {
+    /// Address of the instruction (value of CI during its execution)
+    pub address: i32,
+
+    pub opcode: Opcode,
+
+    /// Address of the word that was read
+    pub operand: i32,
+
+    /// Cycle of the first read
+    pub first_cycle: u64,
+
+    /// Number of times the instruction read the word while it was not set
+    pub count: u64,
+}
+
+impl fmt::Display for UninitializedRead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CI={}: {} {} read line {}, which was never set (first at cycle {}, {} time{})",
+            self.address,
+            self.opcode,
+            self.operand,
+            self.operand,
+            self.first_cycle,
+            self.count,
+            if self.count > 1 { "s" } else { "" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ssem::opcode::Opcode;
+    use crate::ssem::simulator::{Simulator, StopReason};
+    use crate::ssem::testing::TempDir;
+
+    use super::UninitializedRead;
+
+    #[test]
+    fn reads() {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "reads.asm",
+            "00 NUM 0\n01 LDN 20\n02 STO 21\n03 SUB 21\n04 LDN 0\n05 STP\n",
+        );
+        let (mut simulator, source_map) = Simulator::from_file_mapped(&path);
+        simulator.enable_uninitialized_tracking(&source_map);
+        simulator.run(10);
+        assert_eq!(simulator.stop_reason(), Some(StopReason::Halted));
+
+        // Line 21 is read after STO set it, line 0 is set by the file
+        assert_eq!(
+            simulator.uninitialized_reads(),
+            &[UninitializedRead {
+                address: 1,
+                opcode: Opcode::LDN,
+                operand: 20,
+                first_cycle: 0,
+                count: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn repeated_reads() {
+        // Line 30 is read by each iteration of the loop, line 29 only once set by STO
+        let dir = TempDir::new();
+        let path = dir.write(
+            "loop.asm",
+            "00 NUM 0\n01 LDN 30\n02 STO 29\n03 SUB 29\n04 JMP 5\n05 NUM 0\n",
+        );
+        let (mut simulator, source_map) = Simulator::from_file_mapped(&path);
+        simulator.enable_uninitialized_tracking(&source_map);
+        simulator.run(12);
+
+        let reads = simulator.uninitialized_reads();
+        assert_eq!(reads.len(), 1);
+        assert_eq!(
+            (reads[0].operand, reads[0].first_cycle, reads[0].count),
+            (30, 0, 3)
+        );
+        assert_eq!(
+            reads[0].to_string(),
+            "CI=1: LDN 30 read line 30, which was never set (first at cycle 0, 3 times)"
+        );
+    }
+}
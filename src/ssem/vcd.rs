@@ -0,0 +1,195 @@
+//! Value Change Dump (VCD) export, to look at a run in a waveform viewer such as GTKWave
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::simulator::{ExecutedInstruction, Observer};
+use super::store::Store;
+use super::timing::TimingModel;
+
+/// What a VCD file records
+#[derive(Debug, Clone)]
+pub struct VcdOptions {
+    /// Store lines recorded along with A, CI and the present instruction
+    pub lines: Vec<i32>,
+
+    /// Only these cycles are recorded
+    pub cycles: RangeInclusive<u64>,
+
+    /// When given, timestamps are in microseconds of simulated time instead of cycles
+    pub timing: Option<TimingModel>,
+}
+
+/// Records the registers and some store lines as 32-bit signals, one timestamp per cycle
+pub struct VcdWriter<W: Write> {
+    output: W,
+    options: VcdOptions,
+
+    /// Last dumped value of each signal: A, CI, PI then the store lines
+    values: Vec<Option<i32>>,
+
+    /// First error encountered, writing stops afterwards
+    error: Option<io::Error>,
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// Creates the writer and writes the header of the file
+    pub fn new(mut output: W, options: VcdOptions) -> io::Result<VcdWriter<W>> {
+        writeln!(output, "$version ssem-simulator $end")?;
+        if options.timing.is_none() {
+            writeln!(output, "$comment one time unit per instruction cycle $end")?;
+        }
+        writeln!(output, "$timescale 1 us $end")?;
+        writeln!(output, "$scope module ssem $end")?;
+        writeln!(output, "$var reg 32 {} A $end", identifier(0))?;
+        writeln!(output, "$var reg 32 {} CI $end", identifier(1))?;
+        writeln!(output, "$var reg 32 {} PI $end", identifier(2))?;
+        for (i, line) in options.lines.iter().enumerate() {
+            writeln!(
+                output,
+                "$var reg 32 {} S{:02} $end",
+                identifier(i + 3),
+                line
+            )?;
+        }
+        writeln!(output, "$upscope $end")?;
+        writeln!(output, "$enddefinitions $end")?;
+
+        Ok(VcdWriter {
+            output,
+            values: vec![None; options.lines.len() + 3],
+            options,
+            error: None,
+        })
+    }
+
+    fn write(&mut self, instruction: &ExecutedInstruction, store: &Store) -> io::Result<()> {
+        let first = self.values[0].is_none();
+        let time = match self.options.timing {
+            Some(timing) => timing.duration(instruction.cycle).as_micros() as u64,
+            None => instruction.cycle,
+        };
+        writeln!(self.output, "#{time}")?;
+        if first {
+            writeln!(self.output, "$dumpvars")?;
+        }
+
+        // CI as left by the instruction, which depends on the profile and jumps, not the address of the instruction
+        let signals = [instruction.a_after, instruction.ci_after, instruction.word]
+            .into_iter()
+            .chain(self.options.lines.iter().map(|line| store[*line]));
+        for (i, value) in signals.enumerate() {
+            if self.values[i] != Some(value) {
+                writeln!(self.output, "b{:b} {}", value as u32, identifier(i))?;
+                self.values[i] = Some(value);
+            }
+        }
+
+        if first {
+            writeln!(self.output, "$end")?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Observer for VcdWriter<W> {
+    fn instruction_executed(&mut self, instruction: &ExecutedInstruction, store: &Store) {
+        if self.error.is_none() && self.options.cycles.contains(&instruction.cycle) {
+            if let Err(e) = self.write(instruction, store) {
+                eprintln!("Unable to write the VCD file: {e}");
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl<W: Write> Drop for VcdWriter<W> {
+    fn drop(&mut self) {
+        if self.error.is_none() {
+            if let Err(e) = self.output.flush() {
+                eprintln!("Unable to write the VCD file: {e}");
+            }
+        }
+    }
+}
+
+/// Short identifier of a signal, made of printable ASCII characters
+fn identifier(index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - b'!' + 1) as usize;
+
+    let mut index = index;
+    let mut identifier = String::new();
+    loop {
+        identifier.push((FIRST + (index % COUNT) as u8) as char);
+        index /= COUNT;
+        if index == 0 {
+            break;
+        }
+        index -= 1;
+    }
+    identifier
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crate::ssem::opcode::Opcode;
+    use crate::ssem::simulator::{ExecutedInstruction, Observer, Simulator};
+    use crate::ssem::timing::TimingModel;
+
+    use super::{identifier, VcdOptions, VcdWriter};
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier(0), "!");
+        assert_eq!(identifier(93), "~");
+        assert_eq!(identifier(94), "!!");
+    }
+
+    #[test]
+    fn changes_only() {
+        let simulator = Simulator::from_file(Path::new("samples/ssem/fibonacci.asm"));
+        let options = VcdOptions {
+            lines: vec![31],
+            cycles: 0..=10,
+            timing: Some(TimingModel {
+                beats_per_instruction: 4,
+                beat_duration: Duration::from_micros(300),
+            }),
+        };
+        let mut writer = VcdWriter::new(Vec::new(), options).unwrap();
+        let instruction = ExecutedInstruction {
+            cycle: 2,
+            address: 3,
+            word: 24607,
+            opcode: Opcode::STO,
+            operand: 31,
+            operand_value: None,
+            a_before: -1,
+            a_after: -1,
+            ci_after: 3,
+            next_address: 4,
+        };
+        writer.instruction_executed(&instruction, &simulator.store);
+        writer.instruction_executed(
+            &ExecutedInstruction {
+                cycle: 3,
+                address: 4,
+                ci_after: 9,
+                ..instruction
+            },
+            &simulator.store,
+        );
+
+        let output = String::from_utf8(writer.output.clone()).unwrap();
+        let body = output.split("$enddefinitions $end\n").nth(1).unwrap();
+        assert_eq!(
+            body,
+            "#2400\n$dumpvars\nb11111111111111111111111111111111 !\nb11 \"\nb110000000011111 #\nb0 $\n$end\n#3600\nb1001 \"\n"
+        );
+        assert!(output.contains("$var reg 32 $ S31 $end"));
+    }
+}